@@ -0,0 +1,455 @@
+use log::{info, warn};
+use std::{
+    fmt,
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+use super::{
+    address,
+    metrics::Metrics,
+};
+
+/// A binary trie of networks, a lookup takes at most one step per bit of the address.
+#[derive(Default)]
+struct Trie {
+    /// Children by the next bit, 0 for none, the root is node 0.
+    nodes:    Vec<[u32; 2]>,
+    /// Whether a network ends at the node.
+    networks: Vec<bool>,
+}
+
+impl Trie {
+    fn new() -> Self {
+        Self {
+            nodes:    vec![[0, 0]],
+            networks: vec![false],
+        }
+    }
+
+    /// Add the network of the first `length` bits of `bits`.
+    fn insert(
+        &mut self,
+        bits: u128,
+        length: u8,
+    ) {
+        let mut node = 0;
+        for depth in 0..u32::from(length) {
+            if self.networks[node] {
+                return;
+            }
+            let bit = ((bits >> (127 - depth)) & 1) as usize;
+            if self.nodes[node][bit] == 0 {
+                self.nodes.push([0, 0]);
+                self.networks.push(false);
+                self.nodes[node][bit] = (self.nodes.len() - 1) as u32;
+            }
+            node = self.nodes[node][bit] as usize;
+        }
+        self.networks[node] = true;
+    }
+
+    /// Whether any network contains the address `bits` of `width` bits.
+    fn contains(
+        &self,
+        bits: u128,
+        width: u32,
+    ) -> bool {
+        let mut node = 0;
+        for depth in 0..width {
+            if self.networks[node] {
+                return true;
+            }
+            let bit = ((bits >> (127 - depth)) & 1) as usize;
+            node = match self.nodes[node][bit] {
+                0 => return false,
+                child => child as usize,
+            };
+        }
+        self.networks[node]
+    }
+}
+
+/// The networks of a list file, separated by address family.
+struct Networks {
+    ipv4: Trie,
+    ipv6: Trie,
+}
+
+/// The bits of `ip`, most significant first, and their count, with IPv4-mapped addresses as IPv4.
+fn bits(ip: IpAddr) -> (bool, u128, u32) {
+    match address::network(ip, 32, 128).0 {
+        IpAddr::V4(ip) => (true, u128::from(u32::from(ip)) << 96, 32),
+        IpAddr::V6(ip) => (false, u128::from(ip), 128),
+    }
+}
+
+impl Networks {
+    /// Parse one network per line, e.g. `192.0.2.0/24`, `2001:db8::/32` or a single address.
+    /// Empty lines and comments after `#` are skipped.
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut networks = Self {
+            ipv4: Trie::new(),
+            ipv6: Trie::new(),
+        };
+        for (number, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = || format!("line: {}, error: \"invalid network: {}\"", number + 1, line);
+            let mut parts = line.splitn(2, '/');
+            let ip: IpAddr = parts.next().unwrap_or("").parse().map_err(|_| invalid())?;
+            let (ipv4, bits, width) = bits(ip);
+            let length = match parts.next() {
+                Some(length) => length.parse::<u8>().map_err(|_| invalid())?,
+                None => width as u8,
+            };
+            let length = match ip {
+                IpAddr::V6(_) if ipv4 => length.checked_sub(96).ok_or_else(invalid)?,
+                _ => length,
+            };
+            if u32::from(length) > width {
+                return Err(invalid());
+            }
+            if ipv4 {
+                networks.ipv4.insert(bits, length);
+            } else {
+                networks.ipv6.insert(bits, length);
+            }
+        }
+        Ok(networks)
+    }
+
+    fn contains(
+        &self,
+        ip: IpAddr,
+    ) -> bool {
+        match bits(ip) {
+            (true, bits, width) => self.ipv4.contains(bits, width),
+            (false, bits, width) => self.ipv6.contains(bits, width),
+        }
+    }
+}
+
+/// A list file, optionally for a single port, e.g. `2222=bad.txt`.
+/// Paths may contain `=` as well, unless the part before the first one is a port number.
+#[derive(Clone, Debug)]
+pub(crate) struct ListFile {
+    port: Option<u16>,
+    path: PathBuf,
+}
+
+impl FromStr for ListFile {
+    type Err = String;
+
+    fn from_str(file: &str) -> Result<Self, Self::Err> {
+        let (port, path) = match file.find('=') {
+            Some(separator) => match file[..separator].parse() {
+                Ok(port) => (Some(port), &file[separator + 1..]),
+                Err(_) => (None, file),
+            },
+            None => (None, file),
+        };
+        if path.is_empty() {
+            return Err(format!("empty path of list file: {}", file));
+        }
+        Ok(Self {
+            port,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl fmt::Display for ListFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.port {
+            Some(port) => write!(f, "{}={}", port, self.path.display()),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+/// The loaded networks of the list files by port.
+#[derive(Default)]
+struct Lists {
+    allow: Vec<(Option<u16>, Networks)>,
+    deny:  Vec<(Option<u16>, Networks)>,
+}
+
+impl Lists {
+    fn load(
+        allow: &[ListFile],
+        deny: &[ListFile],
+    ) -> Result<Self, String> {
+        let load = |files: &[ListFile]| files
+            .iter()
+            .map(|file| {
+                fs::read_to_string(&file.path)
+                    .map_err(|err| format!("access list, file: {}, error: {}", file, err))
+                    .and_then(|content| Networks::parse(&content)
+                        .map_err(|err| format!("access list, file: {}, {}", file, err)))
+                    .map(|networks| (file.port, networks))
+            })
+            .collect::<Result<Vec<_>, String>>();
+        Ok(Self {
+            allow: load(allow)?,
+            deny:  load(deny)?,
+        })
+    }
+}
+
+/// Whether any of `lists` applies to `port` and whether `ip` is on one of these.
+fn lookup(
+    lists: &[(Option<u16>, Networks)],
+    ip: IpAddr,
+    port: u16,
+) -> Option<bool> {
+    let mut lists = lists
+        .iter()
+        .filter(|(list_port, _)| list_port.map_or(true, |list_port| list_port == port))
+        .peekable();
+    lists.peek()?;
+    Some(lists.any(|(_, networks)| networks.contains(ip)))
+}
+
+/// Networks excluded from the tarpit and networks the tarpit is limited to.
+pub(crate) struct AccessLists {
+    allow_files:  Vec<ListFile>,
+    deny_files:   Vec<ListFile>,
+    lists:        RwLock<Lists>,
+}
+
+impl AccessLists {
+    pub(crate) fn new(
+        allow_files: Vec<ListFile>,
+        deny_files: Vec<ListFile>,
+    ) -> Result<Self, String> {
+        let lists = Lists::load(&allow_files, &deny_files)?;
+        Ok(Self {
+            allow_files,
+            deny_files,
+            lists: RwLock::new(lists),
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.allow_files.is_empty() && self.deny_files.is_empty()
+    }
+
+    /// Load the list files again, the former lists are kept on errors.
+    pub(crate) fn reload(&self) {
+        match Lists::load(&self.allow_files, &self.deny_files) {
+            Ok(lists) => {
+                let mut guard = match self.lists.write() {
+                    Ok(guard) => guard,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+                *guard = lists;
+                info!("access lists, reloaded: {}", self);
+            },
+            Err(err) => warn!("{}, reload: skipped", err),
+        }
+    }
+
+    /// Reload the list files on every SIGHUP.
+    /// The files are opened anew, so they have to stay readable after dropping privileges and within the chroot,
+    /// and reloading fails, once the process is sandboxed.
+    #[cfg(unix)]
+    pub(crate) async fn reload_on_hangup(&self) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => return warn!("signal(), error: {}", err),
+        };
+        while hangup.recv().await.is_some() {
+            self.reload();
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) async fn reload_on_hangup(&self) {}
+
+    /// Reload the list files on every SIGHUP.
+    pub(crate) fn spawn_reload(
+        self: &Arc<Self>,
+        runtime: &tokio::runtime::Runtime,
+    ) {
+        let lists = self.clone();
+        runtime.spawn(async move {
+            lists.reload_on_hangup().await;
+        });
+    }
+
+    /// Whether `ip` is on an allow list for `port`, uncounted.
+    pub(crate) fn allows(
+        &self,
+        ip: IpAddr,
+        port: u16,
+    ) -> bool {
+        let lists = match self.lists.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        lookup(&lists.allow, ip, port).unwrap_or(false)
+    }
+
+    /// The allow list files, separated by spaces.
+    pub(crate) fn allow_files(&self) -> String {
+        files(&self.allow_files)
+    }
+
+    /// Why a client at `ip` to a listener on `port` is excluded from the tarpit, if it is.
+    pub(crate) fn exclude(
+        &self,
+        ip: IpAddr,
+        port: u16,
+        metrics: &Metrics,
+    ) -> Option<&'static str> {
+        if self.is_empty() {
+            return None;
+        }
+        let lists = match self.lists.read() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(matched) = lookup(&lists.deny, ip, port) {
+            metrics.access_list("deny", matched);
+            if matched {
+                return Some("denied");
+            }
+        }
+        if let Some(matched) = lookup(&lists.allow, ip, port) {
+            metrics.access_list("allow", matched);
+            if !matched {
+                return Some("not allowed");
+            }
+        }
+        None
+    }
+}
+
+/// The list files, separated by spaces.
+fn files(files: &[ListFile]) -> String {
+    files.iter().map(ToString::to_string).collect::<Vec<_>>().join(" ")
+}
+
+impl fmt::Display for AccessLists {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "allow: [{}], deny: [{}]", files(&self.allow_files), files(&self.deny_files))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(content: &str) -> Networks {
+        Networks::parse(content).unwrap()
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn default_routes() {
+        let ipv4 = networks("0.0.0.0/0");
+        assert!(ipv4.contains(ip("0.0.0.0")));
+        assert!(ipv4.contains(ip("255.255.255.255")));
+        assert!(ipv4.contains(ip("::ffff:192.0.2.1")));
+        assert!(!ipv4.contains(ip("2001:db8::1")));
+        let ipv6 = networks("::/0");
+        assert!(ipv6.contains(ip("::")));
+        assert!(ipv6.contains(ip("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff")));
+        assert!(!ipv6.contains(ip("192.0.2.1")));
+    }
+
+    #[test]
+    fn single_addresses() {
+        let networks = networks("192.0.2.1/32\n2001:db8::1/128\n198.51.100.7\n2001:db8::7");
+        assert!(networks.contains(ip("192.0.2.1")));
+        assert!(!networks.contains(ip("192.0.2.0")));
+        assert!(!networks.contains(ip("192.0.2.2")));
+        assert!(networks.contains(ip("2001:db8::1")));
+        assert!(!networks.contains(ip("2001:db8::")));
+        assert!(!networks.contains(ip("2001:db8::2")));
+        assert!(networks.contains(ip("198.51.100.7")));
+        assert!(networks.contains(ip("2001:db8::7")));
+    }
+
+    #[test]
+    fn mapped_ipv4() {
+        let networks = networks("192.0.2.0/24\n::ffff:198.51.100.0/120");
+        assert!(networks.contains(ip("::ffff:192.0.2.255")));
+        assert!(networks.contains(ip("198.51.100.1")));
+        assert!(networks.contains(ip("::ffff:198.51.100.1")));
+        assert!(!networks.contains(ip("::ffff:198.51.101.1")));
+        assert!(!networks.contains(ip("::192.0.2.1")));
+        assert!(Networks::parse("::ffff:198.51.100.0/95").is_err());
+    }
+
+    #[test]
+    fn overlapping_prefixes() {
+        for content in &["10.0.0.0/8\n10.1.0.0/16", "10.1.0.0/16\n10.0.0.0/8"] {
+            let networks = networks(content);
+            assert!(networks.contains(ip("10.1.2.3")));
+            assert!(networks.contains(ip("10.2.0.0")));
+            assert!(!networks.contains(ip("11.0.0.0")));
+        }
+        let networks = networks("2001:db8::/32\n2001:db8:1::/48\n2001:db8:1::1/128");
+        assert!(networks.contains(ip("2001:db8:1::1")));
+        assert!(networks.contains(ip("2001:db8:ffff::")));
+        assert!(!networks.contains(ip("2001:db9::")));
+    }
+
+    #[test]
+    fn unaligned_prefixes() {
+        let networks = networks("192.0.2.77/25");
+        assert!(networks.contains(ip("192.0.2.0")));
+        assert!(networks.contains(ip("192.0.2.127")));
+        assert!(!networks.contains(ip("192.0.2.128")));
+    }
+
+    #[test]
+    fn comments_and_errors() {
+        let networks = networks("# bad\n\n  192.0.2.0/24  # office\n");
+        assert!(networks.contains(ip("192.0.2.1")));
+        for content in &["192.0.2.0/33", "2001:db8::/129", "192.0.2.0/", "192.0.2.0/x", "example.com", "192.0.2.0/24/1"] {
+            assert!(Networks::parse(content).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn lookup_by_port() {
+        let lists = vec![
+            (Some(2222), networks("192.0.2.0/24")),
+            (Some(2223), networks("198.51.100.0/24")),
+        ];
+        assert_eq!(lookup(&lists, ip("192.0.2.1"), 2222), Some(true));
+        assert_eq!(lookup(&lists, ip("192.0.2.1"), 2223), Some(false));
+        assert_eq!(lookup(&lists, ip("192.0.2.1"), 22), None);
+        let lists = vec![(None, networks("192.0.2.0/24"))];
+        assert_eq!(lookup(&lists, ip("192.0.2.1"), 22), Some(true));
+    }
+
+    #[test]
+    fn list_files() {
+        let file: ListFile = "2222=bad.txt".parse().unwrap();
+        assert_eq!((file.port, file.path), (Some(2222), PathBuf::from("bad.txt")));
+        let file: ListFile = "bad.txt".parse().unwrap();
+        assert_eq!((file.port, file.path), (None, PathBuf::from("bad.txt")));
+        let file: ListFile = "lists/port=22.txt".parse().unwrap();
+        assert_eq!((file.port, file.path), (None, PathBuf::from("lists/port=22.txt")));
+        let file: ListFile = "22=a=b.txt".parse().unwrap();
+        assert_eq!((file.port, file.path), (Some(22), PathBuf::from("a=b.txt")));
+        let file: ListFile = "70000=bad.txt".parse().unwrap();
+        assert_eq!((file.port, file.path), (None, PathBuf::from("70000=bad.txt")));
+        assert!("".parse::<ListFile>().is_err());
+        assert!("2222=".parse::<ListFile>().is_err());
+    }
+}
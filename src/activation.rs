@@ -0,0 +1,115 @@
+use std::{
+    env,
+    fmt,
+    str::FromStr,
+};
+
+use super::protocol::Protocol;
+
+/// The first file descriptor passed by systemd, `SD_LISTEN_FDS_START`.
+const LISTEN_FDS_START: i32 = 3;
+
+/// An inherited listening socket with an optional protocol, e.g. `3` or `http://4`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ListenFd {
+    pub(crate) protocol:  Protocol,
+    pub(crate) fd:        i32,
+}
+
+impl FromStr for ListenFd {
+    type Err = String;
+
+    fn from_str(fd: &str) -> Result<Self, Self::Err> {
+        let (protocol, fd) = match fd.find("://") {
+            Some(separator) => (fd[..separator].parse()?, &fd[separator + 3..]),
+            None => (Protocol::Ssh, fd),
+        };
+        Ok(Self {
+            protocol,
+            fd: fd
+                .parse()
+                .ok()
+                .filter(|fd| *fd >= 0)
+                .ok_or_else(|| format!("invalid file descriptor: {}", fd))?,
+        })
+    }
+}
+
+impl fmt::Display for ListenFd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://fd{}", self.protocol, self.fd)
+    }
+}
+
+/// The sockets passed by systemd socket activation as per sd_listen_fds(3), with their names and protocols.
+/// Sockets named after a protocol, e.g. `FileDescriptorName=http`, serve that protocol, all others ssh.
+/// The environment variables are removed, so child processes do not inherit them.
+/// Call this before any other thread exists, as changing the environment is not thread safe.
+pub(crate) fn systemd() -> Result<Vec<(String, ListenFd)>, String> {
+    let pid = env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+    if pid != Some(std::process::id()) {
+        return Ok(Vec::new());
+    }
+    let count = env::var("LISTEN_FDS").unwrap_or_default();
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    for variable in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(variable);
+    }
+    let count: i32 = count
+        .parse()
+        .map_err(|err| format!("socket activation, LISTEN_FDS: {:?}, error: {}", count, err))?;
+    let names: Vec<&str> = names.split(':').collect();
+    Ok((0..count.max(0))
+        .map(|index| {
+            let fd = LISTEN_FDS_START + index;
+            let name = names
+                .get(index as usize)
+                .filter(|name| !name.is_empty())
+                .map_or_else(|| format!("fd{}", fd), |name| (*name).to_owned());
+            let protocol = name.parse().unwrap_or(Protocol::Ssh);
+            (name, ListenFd { protocol, fd })
+        })
+        .collect())
+}
+
+/// Take over the inherited listening socket `fd`.
+#[cfg(unix)]
+pub(crate) fn listener(fd: i32) -> Result<std::net::TcpListener, String> {
+    use std::os::unix::io::FromRawFd;
+
+    let error = |error: std::io::Error| format!("listen, fd: {}, error: {}", fd, error);
+    // Neither leak the socket to child processes, nor take over a closed descriptor.
+    // SAFETY: F_SETFD only changes descriptor flags and fails with EBADF for descriptors that are not open.
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(error(std::io::Error::last_os_error()));
+    }
+    let mut accepting: libc::c_int = 0;
+    let mut length = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    // SAFETY: `accepting` and `length` outlive the call and `length` is the size of `accepting`.
+    let result = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_ACCEPTCONN,
+            &mut accepting as *mut libc::c_int as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result == -1 {
+        return Err(error(std::io::Error::last_os_error()));
+    }
+    if accepting == 0 {
+        return Err(format!("listen, fd: {}, error: \"not a listening socket\"", fd));
+    }
+    // SAFETY: `fd` is an open listening socket as checked above, and nothing else in this process owns it,
+    // as every inherited descriptor is taken over only once.
+    let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+    listener.local_addr().map_err(error)?;
+    listener.set_nonblocking(true).map_err(error)?;
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn listener(fd: i32) -> Result<std::net::TcpListener, String> {
+    Err(format!("listen, fd: {}, error: \"inherited sockets are not supported\"", fd))
+}
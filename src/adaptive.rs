@@ -0,0 +1,232 @@
+use log::debug;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::SocketAddr,
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+
+use super::address;
+
+/// Upper bound of remembered client kinds.
+const MAX_ENTRIES: usize = 65536;
+
+/// Stay this much below the learned point, where clients give up.
+const BACKOFF: f64 = 0.8;
+
+/// Weight of a new observation of a client giving up.
+const LEARNING_RATE: f64 = 0.25;
+
+/// Smallest delay to grow from.
+const MIN_DELAY: Duration = Duration::from_millis(100);
+
+/// Largest factor the delay grows by after every response.
+const MAX_GROWTH: f64 = 16.0;
+
+/// Parse a growth factor above 1 and up to 16, e.g. 1.5.
+pub(crate) fn parse_growth(text: &str) -> Result<f64, String> {
+    text.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|growth| *growth > 1.0 && *growth <= MAX_GROWTH)
+        .ok_or_else(|| format!("growth factor not above 1 and up to {}: {}", MAX_GROWTH, text))
+}
+
+/// How clients are grouped to learn their timeouts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum AdaptiveKey {
+    /// Every client gets the same delay.
+    Off,
+    /// By the /24 of IPv4- or the /64 of IPv6-peers.
+    Prefix,
+}
+
+impl AdaptiveKey {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["off", "prefix"];
+}
+
+impl FromStr for AdaptiveKey {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key {
+            "off"     => Ok(Self::Off),
+            "prefix"  => Ok(Self::Prefix),
+            _ => Err(format!("unknown adaptive key: {}", key)),
+        }
+    }
+}
+
+impl fmt::Display for AdaptiveKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off     => "off",
+            Self::Prefix  => "prefix",
+        })
+    }
+}
+
+/// What was learned about a kind of client.
+struct Learned {
+    /// Estimated delay in seconds, after which these clients give up.
+    give_up:  f64,
+    samples:  u64,
+}
+
+/// Learns how long clients wait for a response and keeps the delay just below.
+pub(crate) struct Adaptive {
+    key:        AdaptiveKey,
+    growth:     f64,
+    max_delay:  Duration,
+    learned:    Mutex<HashMap<String, Learned>>,
+}
+
+impl Adaptive {
+    pub(crate) fn new(
+        key:        AdaptiveKey,
+        growth:     f64,
+        max_delay:  Duration,
+    ) -> Self {
+        Self {
+            key,
+            growth: if growth.is_nan() { 1.0 } else { growth.clamp(1.0, MAX_GROWTH) },
+            max_delay,
+            learned: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Start adapting the delay of a single connection, starting at `delay`.
+    pub(crate) fn session(
+        &self,
+        peer:   &SocketAddr,
+        delay:  Duration,
+    ) -> AdaptiveSession<'_> {
+        let key = match self.key {
+            AdaptiveKey::Off    => String::new(),
+            AdaptiveKey::Prefix => address::prefix(peer.ip(), 24, 64),
+        };
+        AdaptiveSession {
+            adaptive: self,
+            delay:    delay.min(self.ceiling(&key)),
+            key,
+            probed:   false,
+        }
+    }
+
+    fn ceiling(
+        &self,
+        key: &str,
+    ) -> Duration {
+        let guard = match self.learned.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        guard
+            .get(key)
+            .map_or(self.max_delay, |learned| {
+                Duration::from_secs_f64(learned.give_up * BACKOFF).min(self.max_delay)
+            })
+    }
+
+    fn learn(
+        &self,
+        key:    &str,
+        delay:  Duration,
+        gave_up: bool,
+    ) {
+        let mut guard = match self.learned.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let seconds = delay.as_secs_f64();
+        if let Some(learned) = guard.get_mut(key) {
+            if gave_up {
+                learned.give_up += LEARNING_RATE * (seconds - learned.give_up);
+                learned.samples += 1;
+            } else if seconds >= learned.give_up * BACKOFF {
+                // The client survived the ceiling, probe a little further.
+                learned.give_up = learned.give_up.max(seconds / BACKOFF) * (1.0 + LEARNING_RATE / 4.0);
+            } else {
+                return;
+            }
+            debug!(
+                "adaptive, key: {}, give_up: {:.2?}, samples: {}",
+                key,
+                Duration::from_secs_f64(learned.give_up.min(self.max_delay.as_secs_f64())),
+                learned.samples,
+            );
+        } else if gave_up {
+            if guard.len() >= MAX_ENTRIES {
+                if let Some(evict) = guard.keys().next().cloned() {
+                    guard.remove(&evict);
+                }
+            }
+            guard.insert(key.to_owned(), Learned { give_up: seconds, samples: 1 });
+            debug!("adaptive, key: {}, give_up: {:.2?}, samples: 1", key, delay);
+        }
+    }
+}
+
+impl fmt::Display for Adaptive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, growth: {}, max_delay: {:.2?}", self.key, self.growth, self.max_delay)
+    }
+}
+
+/// The delay of a single connection.
+pub(crate) struct AdaptiveSession<'a> {
+    adaptive: &'a Adaptive,
+    key:      String,
+    delay:    Duration,
+    probed:   bool,
+}
+
+impl AdaptiveSession<'_> {
+    pub(crate) fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// The client is still connected after `delay`, wait a little longer next time.
+    pub(crate) fn held(&mut self) {
+        let ceiling = self.adaptive.ceiling(&self.key);
+        if !self.probed && self.delay >= ceiling {
+            self.adaptive.learn(&self.key, self.delay, false);
+            self.probed = true;
+        }
+        self.delay = self.delay.max(MIN_DELAY).mul_f64(self.adaptive.growth).min(ceiling);
+    }
+
+    /// The client disconnected after waiting at most `waited`.
+    pub(crate) fn gave_up(
+        &self,
+        waited: Duration,
+    ) {
+        self.adaptive.learn(&self.key, waited, true);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn growth_bounds() {
+        assert_eq!(parse_growth("1.5"), Ok(1.5));
+        assert_eq!(parse_growth("16"), Ok(16.0));
+        for invalid in &["1", "0.5", "-2", "16.5", "1e300", "inf", "NaN", "", "x"] {
+            assert!(parse_growth(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn held_stays_within_ceiling() {
+        let adaptive = Adaptive::new(AdaptiveKey::Off, MAX_GROWTH, Duration::from_secs(60));
+        let mut session = adaptive.session(&"192.0.2.1:22".parse().unwrap(), Duration::from_secs(10));
+        for _ in 0..4 {
+            session.held();
+        }
+        assert_eq!(session.delay(), Duration::from_secs(60));
+    }
+}
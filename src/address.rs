@@ -0,0 +1,34 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// The network of `ip` with the given prefix length for IPv4 or IPv6, e.g. `192.0.2.0/24`.
+pub(crate) fn prefix(
+    ip:         IpAddr,
+    ipv4_bits:  u8,
+    ipv6_bits:  u8,
+) -> String {
+    let (network, bits) = network(ip, ipv4_bits, ipv6_bits);
+    format!("{}/{}", network, bits)
+}
+
+/// The network address of `ip` and the effective prefix length.
+pub(crate) fn network(
+    ip:         IpAddr,
+    ipv4_bits:  u8,
+    ipv6_bits:  u8,
+) -> (IpAddr, u8) {
+    match ip {
+        IpAddr::V4(ip) => {
+            let bits = ipv4_bits.min(32);
+            let mask = u32::MAX.checked_shl(32 - u32::from(bits)).unwrap_or(0);
+            (IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask)), bits)
+        },
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return network(IpAddr::V4(ip), ipv4_bits, ipv6_bits);
+            }
+            let bits = ipv6_bits.min(128);
+            let mask = u128::MAX.checked_shl(128 - u32::from(bits)).unwrap_or(0);
+            (IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask)), bits)
+        },
+    }
+}
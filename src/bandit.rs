@@ -0,0 +1,270 @@
+use log::{info, warn};
+use rand::Rng;
+use std::{
+    collections::BTreeMap,
+    f64::consts::PI,
+    fmt::{self, Write},
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write as _},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+#[cfg(feature = "exporters")]
+use super::metrics;
+
+/// Group of all clients, regardless of their class.
+const ALL: &str = "all";
+
+/// Observations of a group, before preferring them over all clients.
+const MIN_GROUP_PULLS: u64 = 16;
+
+/// Prior variance of the connection time in seconds², before enough observations.
+const PRIOR_VARIANCE: f64 = 3600.0;
+
+/// How to trade exploring banners off against holding clients.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Strategy {
+    /// Choose a random banner with probability epsilon, otherwise the best so far.
+    EpsilonGreedy(f64),
+    /// Choose the banner with the best sample of the posterior of its mean.
+    Thompson,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::EpsilonGreedy(epsilon)  => write!(f, "epsilon-greedy:{}", epsilon),
+            Self::Thompson                => f.write_str("thompson"),
+        }
+    }
+}
+
+/// Observed connection times of clients, who were shown a single banner last.
+#[derive(Clone, Copy, Default)]
+struct Arm {
+    pulls:        u64,
+    sum:          f64,
+    sum_squares:  f64,
+}
+
+impl Arm {
+    fn mean(&self) -> f64 {
+        if self.pulls == 0 {
+            0.0
+        } else {
+            self.sum / self.pulls as f64
+        }
+    }
+
+    fn variance(&self) -> f64 {
+        if self.pulls < 2 {
+            PRIOR_VARIANCE
+        } else {
+            let mean = self.mean();
+            (self.sum_squares / self.pulls as f64 - mean * mean).max(1.0)
+        }
+    }
+}
+
+/// Learns, which banner holds clients longest, by class of the client.
+pub(crate) struct Bandit {
+    strategy: Strategy,
+    state:    Option<PathBuf>,
+    /// The state file, opened before dropping privileges and entering the sandbox.
+    file:     Option<Mutex<File>>,
+    /// Arms by class and banner.
+    arms:     Mutex<BTreeMap<String, BTreeMap<String, Arm>>>,
+}
+
+impl Bandit {
+    /// Start with the learned state of the file `state`, which is created, if it does not exist.
+    pub(crate) fn new(
+        strategy: Strategy,
+        state: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        if let Strategy::EpsilonGreedy(epsilon) = strategy {
+            if !(0.0..=1.0).contains(&epsilon) {
+                return Err(format!("bandit, error: \"epsilon {} not within 0 and 1\"", epsilon));
+            }
+        }
+        let mut arms: BTreeMap<String, BTreeMap<String, Arm>> = BTreeMap::new();
+        let mut handle = None;
+        if let Some(file) = &state {
+            let mut content = String::new();
+            let opened = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(file)
+                .and_then(|mut opened| opened.read_to_string(&mut content).map(|_| opened));
+            match opened {
+                Ok(opened) => handle = Some(Mutex::new(opened)),
+                Err(err) => return Err(format!("bandit, file: {}, error: {}", file.display(), err)),
+            }
+            for (number, line) in content.lines().enumerate() {
+                let fields: Vec<&str> = line.split('\t').collect();
+                let arm = match fields.as_slice() {
+                    [class, banner, pulls, sum, sum_squares] => pulls
+                        .parse()
+                        .ok()
+                        .zip(sum.parse().ok())
+                        .zip(sum_squares.parse().ok())
+                        .map(|((pulls, sum), sum_squares)| (class, banner, Arm { pulls, sum, sum_squares })),
+                    _ => None,
+                };
+                match arm {
+                    Some((class, banner, arm)) => {
+                        arms.entry(class.to_string()).or_default().insert(banner.to_string(), arm);
+                    },
+                    None => return Err(format!("bandit, file: {}, line: {}, error: \"invalid state\"", file.display(), number + 1)),
+                }
+            }
+            info!("bandit, file: {}, groups: {}", file.display(), arms.len());
+        }
+        Ok(Self {
+            strategy,
+            state,
+            file: handle,
+            arms: Mutex::new(arms),
+        })
+    }
+
+    /// A client of `class`, who was shown `banner` last, disconnected after `seconds`.
+    pub(crate) fn observe(
+        &self,
+        class: &str,
+        banner: &str,
+        seconds: f64,
+    ) {
+        let mut arms = match self.arms.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for group in &[ALL, class] {
+            let arm = arms
+                .entry(group.to_string())
+                .or_default()
+                .entry(banner.to_owned())
+                .or_default();
+            arm.pulls       += 1;
+            arm.sum         += seconds;
+            arm.sum_squares += seconds * seconds;
+        }
+    }
+
+    /// Choose one of `banners` for a client of `class`, returns its index.
+    pub(crate) fn choose(
+        &self,
+        class: &str,
+        banners: &[&str],
+    ) -> usize {
+        let arms = match self.arms.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let group = arms
+            .get(class)
+            .filter(|group| group.values().map(|arm| arm.pulls).sum::<u64>() >= MIN_GROUP_PULLS)
+            .or_else(|| arms.get(ALL));
+        let arms: Vec<Arm> = banners
+            .iter()
+            .map(|banner| group.and_then(|group| group.get(*banner)).copied().unwrap_or_default())
+            .collect();
+
+        let mut rng = rand::thread_rng();
+        // Every banner is tried at least once.
+        let untried: Vec<usize> = (0..arms.len()).filter(|&index| arms[index].pulls == 0).collect();
+        if !untried.is_empty() {
+            return untried[rng.gen_range(0, untried.len())];
+        }
+        let score = |arm: &Arm, rng: &mut rand::rngs::ThreadRng| match self.strategy {
+            Strategy::EpsilonGreedy(_) => arm.mean(),
+            Strategy::Thompson => {
+                // Box-Muller transform for a standard normal sample.
+                let radius = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+                let normal = radius * (2.0 * PI * rng.gen::<f64>()).cos();
+                arm.mean() + normal * (arm.variance() / arm.pulls as f64).sqrt()
+            },
+        };
+        if let Strategy::EpsilonGreedy(epsilon) = self.strategy {
+            if rng.gen_bool(epsilon) {
+                return rng.gen_range(0, arms.len());
+            }
+        }
+        let mut best = (0, f64::MIN);
+        for (index, arm) in arms.iter().enumerate() {
+            let score = score(arm, &mut rng);
+            if score > best.1 {
+                best = (index, score);
+            }
+        }
+        best.0
+    }
+
+    /// Write the learned state, if there is a state file.
+    pub(crate) fn save(&self) {
+        let (file, handle) = match (&self.state, &self.file) {
+            (Some(file), Some(handle)) => (file, handle),
+            _ => return,
+        };
+        let mut content = String::new();
+        {
+            let arms = match self.arms.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            for (class, group) in arms.iter() {
+                for (banner, arm) in group {
+                    let _ = writeln!(content, "{}\t{}\t{}\t{}\t{}", class, banner, arm.pulls, arm.sum, arm.sum_squares);
+                }
+            }
+        }
+        let mut handle = match handle.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let written = handle
+            .set_len(0)
+            .and_then(|_| handle.seek(SeekFrom::Start(0)))
+            .and_then(|_| handle.write_all(content.as_bytes()))
+            .and_then(|_| handle.sync_data());
+        if let Err(err) = written {
+            warn!("bandit, file: {}, error: {}", file.display(), err);
+        }
+    }
+
+    /// Append the statistics of every arm to the metrics.
+    #[cfg(feature = "exporters")]
+    pub(crate) fn export(
+        &self,
+        export: &mut String,
+    ) {
+        let arms = match self.arms.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for (name, description, value) in &[
+            ("bandit_pulls_total", "Number of former clients by the banner shown last and their class.", (|arm: &Arm| arm.pulls as f64) as fn(&Arm) -> f64),
+            ("bandit_hold_seconds_mean", "Mean connection time by the banner shown last and the class of the client.", Arm::mean),
+        ] {
+            let kind = if name.ends_with("_total") { "counter" } else { "gauge" };
+            let _ = write!(export, "\n# HELP {} {}\n# TYPE {} {}\n", name, description, name, kind);
+            for (class, group) in arms.iter() {
+                for (banner, arm) in group {
+                    let _ = writeln!(export, "{}{{class=\"{}\",banner=\"{}\"}} {}", name, metrics::escape(class), metrics::escape(banner), value(arm));
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Bandit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.state {
+            Some(file) => write!(f, "{}, state: {}", self.strategy, file.display()),
+            None => write!(f, "{}", self.strategy),
+        }
+    }
+}
@@ -0,0 +1,481 @@
+use rand::{
+    seq::SliceRandom,
+    Rng,
+};
+use std::{
+    fmt,
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use super::bandit::Bandit;
+
+/// A generator of tarpit-lines, shared between all connections.
+pub(crate) trait BannerSource: Send + Sync {
+    /// Short description of the source for the logs.
+    fn describe(&self) -> String;
+
+    /// Start a new banner for a single connection.
+    fn banner(&self) -> Box<dyn Banner + Send + '_>;
+}
+
+/// The banner of a single connection.
+pub(crate) trait Banner {
+    /// The next line, terminated by CRLF, or `None` at the end of a banner-loop.
+    fn next_line(&mut self) -> Option<Vec<u8>>;
+}
+
+/// Which `BannerSource` to use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum BannerKind {
+    /// The lines of the message-file in order.
+    Static,
+    /// Random printable lines, like endlessh.
+    Random,
+    /// A random entry of a `%`-separated fortune-file.
+    Fortune,
+    /// The lines of the message-file in random order.
+    Shuffle,
+}
+
+impl BannerKind {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["static", "random", "fortune", "shuffle"];
+}
+
+impl FromStr for BannerKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "static"  => Ok(Self::Static),
+            "random"  => Ok(Self::Random),
+            "fortune" => Ok(Self::Fortune),
+            "shuffle" => Ok(Self::Shuffle),
+            _ => Err(format!("unknown banner: {}", kind)),
+        }
+    }
+}
+
+impl fmt::Display for BannerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Static  => "static",
+            Self::Random  => "random",
+            Self::Fortune => "fortune",
+            Self::Shuffle => "shuffle",
+        })
+    }
+}
+
+/// How connections choose between several banners.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Rotation {
+    /// A random banner.
+    Random,
+    /// One banner after the other.
+    RoundRobin,
+    /// Mostly the banner holding clients longest so far, sometimes a random one.
+    EpsilonGreedy,
+    /// Thompson sampling of the banner holding clients longest.
+    Thompson,
+}
+
+impl Rotation {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["random", "round-robin", "epsilon-greedy", "thompson"];
+}
+
+impl FromStr for Rotation {
+    type Err = String;
+
+    fn from_str(rotation: &str) -> Result<Self, Self::Err> {
+        match rotation {
+            "random"      => Ok(Self::Random),
+            "round-robin" => Ok(Self::RoundRobin),
+            "epsilon-greedy" => Ok(Self::EpsilonGreedy),
+            "thompson"    => Ok(Self::Thompson),
+            _ => Err(format!("unknown banner rotation: {}", rotation)),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Random      => "random",
+            Self::RoundRobin  => "round-robin",
+            Self::EpsilonGreedy => "epsilon-greedy",
+            Self::Thompson    => "thompson",
+        })
+    }
+}
+
+/// The default message, if no file was given.
+const DEFAULT_MESSAGE: &str = concat!(
+    "My name is Yon Yonson\n",
+    "I live in Wisconsin.\n",
+    "There, the people I meet\n",
+    "As I walk down the street\n",
+    "Say “Hey, what’s your name?”\n",
+    "And I say:\n",
+);
+
+/// Read the message-files, directories are read file by file in order of their names.
+/// Returns the banner id, the name of the file without extension, and the content of every file.
+pub(crate) fn read(paths: &[String]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut files = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<_>, _>>())
+                .map_err(|err| format!("message, directory: {}, error: {}", path.display(), err))?;
+            entries.retain(|entry| entry.is_file());
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    let mut messages: Vec<(String, Vec<u8>)> = Vec::with_capacity(files.len());
+    for file in files {
+        let id = file
+            .file_stem()
+            .map_or_else(|| file.display().to_string(), |stem| stem.to_string_lossy().into_owned());
+        if id.chars().any(char::is_control) {
+            return Err(format!("message, file: {}, error: \"control characters in banner id: {}\"", file.display(), id.escape_debug()));
+        }
+        if messages.iter().any(|(other, _)| *other == id) {
+            return Err(format!("message, file: {}, error: \"duplicate banner id: {}\"", file.display(), id));
+        }
+        let content = fs::read(&file)
+            .map_err(|err| format!("message, file: {}, error: {}", file.display(), err))?;
+        messages.push((id, content));
+    }
+    Ok(messages)
+}
+
+/// Create the `BannerSource` of `kind` from the content of the message-file,
+/// random lines are kept within `max_length` bytes.
+fn source(
+    kind:       BannerKind,
+    message:    Option<&str>,
+    max_length: usize,
+) -> Result<Box<dyn BannerSource>, String> {
+    let message = message.unwrap_or(DEFAULT_MESSAGE);
+    let source: Box<dyn BannerSource> = match kind {
+        BannerKind::Static  => Box::new(StaticBanner  { lines: lines(message) }),
+        BannerKind::Shuffle => Box::new(ShuffleBanner { lines: lines(message) }),
+        BannerKind::Random  => Box::new(RandomBanner  { max_length: max_length.clamp(5, 34) - 2 }),
+        BannerKind::Fortune => Box::new(FortuneBanner { fortunes: fortunes(message) }),
+    };
+    let empty = source.banner().next_line().is_none();
+    if empty {
+        Err(format!("banner, kind: {}, error: \"empty message\"", kind))
+    } else {
+        Ok(source)
+    }
+}
+
+/// All banners by their id and how connections choose between them.
+pub(crate) struct Banners {
+    sources:  Vec<(String, Box<dyn BannerSource>)>,
+    rotation: Rotation,
+    switch:   bool,
+    next:     AtomicUsize,
+    bandit:   Option<Arc<Bandit>>,
+}
+
+impl Banners {
+    /// Create a banner of `kind` for every message, `(id, content)`, or the default banner without messages.
+    /// The `bandit` chooses the banners, if given.
+    pub(crate) fn new(
+        kind:       BannerKind,
+        messages:   &[(String, String)],
+        max_length: usize,
+        rotation:   Rotation,
+        switch:     bool,
+        bandit:     Option<Arc<Bandit>>,
+    ) -> Result<Self, String> {
+        let sources = if messages.is_empty() || kind == BannerKind::Random {
+            vec![(kind.to_string(), source(kind, None, max_length)?)]
+        } else {
+            messages
+                .iter()
+                .map(|(id, message)| {
+                    source(kind, Some(message), max_length)
+                        .map(|source| (id.clone(), source))
+                        .map_err(|err| format!("{}, id: {}", err, id))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        Ok(Self {
+            sources,
+            rotation,
+            switch,
+            next: AtomicUsize::new(0),
+            bandit,
+        })
+    }
+
+    /// Choose a banner for a client of `class`, other than `current` if possible, unless learning.
+    fn choose(
+        &self,
+        current: Option<usize>,
+        class: &str,
+    ) -> usize {
+        let count = self.sources.len();
+        match (self.rotation, current) {
+            _ if count == 1 => 0,
+            _ if self.bandit.is_some() => self.bandit.as_ref().map_or(0, |bandit| {
+                let ids: Vec<&str> = self.sources.iter().map(|(id, _)| id.as_str()).collect();
+                bandit.choose(class, &ids)
+            }),
+            (Rotation::Random, None) => rand::thread_rng().gen_range(0, count),
+            (Rotation::Random, Some(current)) => (current + rand::thread_rng().gen_range(1, count)) % count,
+            (Rotation::RoundRobin, _) | (Rotation::EpsilonGreedy, _) | (Rotation::Thompson, _) => {
+                self.next.fetch_add(1, Ordering::Relaxed) % count
+            },
+        }
+    }
+
+    /// Start the banner of a single connection of a client of `class`.
+    pub(crate) fn start(
+        &self,
+        class: &str,
+    ) -> Rotating<'_> {
+        let current = self.choose(None, class);
+        Rotating {
+            banners: self,
+            current,
+            lines:   self.sources[current].1.banner(),
+        }
+    }
+}
+
+impl fmt::Display for Banners {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.bandit {
+            Some(bandit) => write!(f, "rotation: {}, switch: {}, banners: ", bandit, self.switch)?,
+            None => write!(f, "rotation: {}, switch: {}, banners: ", self.rotation, self.switch)?,
+        }
+        for (index, (id, source)) in self.sources.iter().enumerate() {
+            if index > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{} ({})", id, source.describe())?;
+        }
+        Ok(())
+    }
+}
+
+/// The banner of a single connection, which may switch to another banner after every banner-loop.
+pub(crate) struct Rotating<'a> {
+    banners:  &'a Banners,
+    current:  usize,
+    lines:    Box<dyn Banner + Send + 'a>,
+}
+
+impl Rotating<'_> {
+    /// Id of the current banner.
+    pub(crate) fn id(&self) -> &str {
+        &self.banners.sources[self.current].0
+    }
+
+    /// Switch to another banner at the end of a banner-loop, if enabled, returns whether the banner changed.
+    pub(crate) fn rotate(
+        &mut self,
+        class: &str,
+    ) -> bool {
+        if !self.banners.switch {
+            return false;
+        }
+        let next = self.banners.choose(Some(self.current), class);
+        if next == self.current {
+            return false;
+        }
+        self.current = next;
+        self.lines = self.banners.sources[next].1.banner();
+        true
+    }
+}
+
+impl Banner for Rotating<'_> {
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        self.lines.next_line()
+    }
+}
+
+fn lines(message: &str) -> Vec<Vec<u8>> {
+    message
+        .lines()
+        .map(crlf)
+        .collect()
+}
+
+fn fortunes(message: &str) -> Vec<Vec<Vec<u8>>> {
+    let mut fortunes = vec![Vec::new()];
+    for line in message.lines() {
+        if line.trim_end() == "%" {
+            fortunes.push(Vec::new());
+        } else if let Some(fortune) = fortunes.last_mut() {
+            fortune.push(crlf(line));
+        }
+    }
+    fortunes.retain(|fortune| !fortune.is_empty());
+    fortunes
+}
+
+fn crlf(line: &str) -> Vec<u8> {
+    let mut line = line.trim_end_matches('\r').as_bytes().to_vec();
+    line.extend_from_slice(b"\r\n");
+    line
+}
+
+/// Lines of the message-file in order.
+struct StaticBanner {
+    lines: Vec<Vec<u8>>,
+}
+
+impl BannerSource for StaticBanner {
+    fn describe(&self) -> String {
+        format!("static, lines: {}", self.lines.len())
+    }
+
+    fn banner(&self) -> Box<dyn Banner + Send + '_> {
+        Box::new(LineBanner {
+            lines: &self.lines,
+            order: (0..self.lines.len()).collect(),
+            index: 0,
+            shuffle: false,
+        })
+    }
+}
+
+/// Lines of the message-file in a new order on every banner-loop.
+struct ShuffleBanner {
+    lines: Vec<Vec<u8>>,
+}
+
+impl BannerSource for ShuffleBanner {
+    fn describe(&self) -> String {
+        format!("shuffle, lines: {}", self.lines.len())
+    }
+
+    fn banner(&self) -> Box<dyn Banner + Send + '_> {
+        let mut order: Vec<usize> = (0..self.lines.len()).collect();
+        order.shuffle(&mut rand::thread_rng());
+        Box::new(LineBanner {
+            lines: &self.lines,
+            order,
+            index: 0,
+            shuffle: true,
+        })
+    }
+}
+
+struct LineBanner<'a> {
+    lines:    &'a [Vec<u8>],
+    order:    Vec<usize>,
+    index:    usize,
+    shuffle:  bool,
+}
+
+impl Banner for LineBanner<'_> {
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        if let Some(&line) = self.order.get(self.index) {
+            self.index += 1;
+            Some(self.lines[line].clone())
+        } else {
+            self.index = 0;
+            if self.shuffle {
+                self.order.shuffle(&mut rand::thread_rng());
+            }
+            None
+        }
+    }
+}
+
+/// A random fortune on every banner-loop.
+struct FortuneBanner {
+    fortunes: Vec<Vec<Vec<u8>>>,
+}
+
+impl BannerSource for FortuneBanner {
+    fn describe(&self) -> String {
+        format!("fortune, fortunes: {}", self.fortunes.len())
+    }
+
+    fn banner(&self) -> Box<dyn Banner + Send + '_> {
+        Box::new(FortuneLines {
+            fortunes: &self.fortunes,
+            current:  self.fortunes.choose(&mut rand::thread_rng()),
+            index:    0,
+        })
+    }
+}
+
+struct FortuneLines<'a> {
+    fortunes: &'a [Vec<Vec<u8>>],
+    current:  Option<&'a Vec<Vec<u8>>>,
+    index:    usize,
+}
+
+impl Banner for FortuneLines<'_> {
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        if let Some(line) = self.current.and_then(|fortune| fortune.get(self.index)) {
+            self.index += 1;
+            Some(line.clone())
+        } else {
+            self.index = 0;
+            self.current = self.fortunes.choose(&mut rand::thread_rng());
+            None
+        }
+    }
+}
+
+/// Random lines of printable characters, like endlessh.
+struct RandomBanner {
+    max_length: usize,
+}
+
+impl BannerSource for RandomBanner {
+    fn describe(&self) -> String {
+        format!("random, max_length: {}", self.max_length)
+    }
+
+    fn banner(&self) -> Box<dyn Banner + Send + '_> {
+        Box::new(RandomLines {
+            max_length: self.max_length,
+            done:       false,
+        })
+    }
+}
+
+struct RandomLines {
+    max_length: usize,
+    done:       bool,
+}
+
+impl Banner for RandomLines {
+    fn next_line(&mut self) -> Option<Vec<u8>> {
+        self.done = !self.done;
+        if self.done {
+            let mut rng = rand::thread_rng();
+            let length = rng.gen_range(3, self.max_length + 1);
+            let mut line: Vec<u8> = (0..length).map(|_| rng.gen_range(b' ', b'~' + 1)).collect();
+            if line.starts_with(b"SSH-") {
+                line[0] = b'X';
+            }
+            line.extend_from_slice(b"\r\n");
+            Some(line)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use rand::Rng;
+use std::{
+    fmt,
+    str::FromStr,
+};
+
+/// Largest chunk size, which also sizes the socket send buffer.
+const MAX_CHUNK_SIZE: usize = u16::MAX as usize;
+
+/// How to split a line into the chunks sent between two delays.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Chunking {
+    /// `fixed:<n>`, chunks of `n` bytes.
+    Fixed(usize),
+    /// `byte`, a single byte per chunk.
+    Byte,
+    /// `line`, a whole line per chunk.
+    Line,
+    /// `random:<min>-<max>`, chunks of a random size between `min` and `max` bytes.
+    Random(usize, usize),
+}
+
+impl Chunking {
+    /// Size of the socket send buffer fitting the chunks of this strategy.
+    pub(crate) fn send_buffer_size(
+        &self,
+        max_line_length: usize,
+    ) -> usize {
+        match *self {
+            Self::Fixed(size)       => size,
+            Self::Byte              => 1,
+            Self::Line              => max_line_length,
+            Self::Random(_, size)   => size,
+        }
+    }
+
+    /// Split `line` into chunks.
+    pub(crate) fn split<'a>(
+        &self,
+        line: &'a [u8],
+    ) -> Vec<&'a [u8]> {
+        match *self {
+            Self::Fixed(size)   => line.chunks(size).collect(),
+            Self::Byte          => line.chunks(1).collect(),
+            Self::Line          => vec![line],
+            Self::Random(minimum, maximum) => {
+                let mut rng = rand::thread_rng();
+                let mut chunks = Vec::new();
+                let mut rest = line;
+                while !rest.is_empty() {
+                    let size = rng.gen_range(minimum, maximum.saturating_add(1)).min(rest.len());
+                    let (chunk, tail) = rest.split_at(size);
+                    chunks.push(chunk);
+                    rest = tail;
+                }
+                chunks
+            },
+        }
+    }
+}
+
+impl FromStr for Chunking {
+    type Err = String;
+
+    fn from_str(chunking: &str) -> Result<Self, Self::Err> {
+        let size = |size: &str| match size.parse::<usize>() {
+            Ok(size) if size > 0 && size <= MAX_CHUNK_SIZE => Ok(size),
+            _ => Err(format!("invalid chunk size, expected 1 to {}: {}", MAX_CHUNK_SIZE, chunking)),
+        };
+        let (strategy, parameters) = match chunking.find(':') {
+            Some(colon) => (&chunking[..colon], &chunking[colon + 1..]),
+            None => (chunking, ""),
+        };
+        match strategy {
+            "fixed" => Ok(Self::Fixed(size(parameters)?)),
+            "byte"  => Ok(Self::Byte),
+            "line"  => Ok(Self::Line),
+            "random" => {
+                let dash = parameters
+                    .find('-')
+                    .ok_or_else(|| format!("expected random:<min>-<max>, got: {}", chunking))?;
+                let minimum = size(&parameters[..dash])?;
+                let maximum = size(&parameters[dash + 1..])?;
+                if minimum > maximum {
+                    Err(format!("minimum exceeds maximum: {}", chunking))
+                } else {
+                    Ok(Self::Random(minimum, maximum))
+                }
+            },
+            _ => Err(format!("unknown chunking: {}", chunking)),
+        }
+    }
+}
+
+impl fmt::Display for Chunking {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Fixed(size)               => write!(f, "fixed:{}", size),
+            Self::Byte                      => f.write_str("byte"),
+            Self::Line                      => f.write_str("line"),
+            Self::Random(minimum, maximum)  => write!(f, "random:{}-{}", minimum, maximum),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_bounds() {
+        assert_eq!("fixed:65535".parse(), Ok(Chunking::Fixed(MAX_CHUNK_SIZE)));
+        assert_eq!("random:1-65535".parse(), Ok(Chunking::Random(1, MAX_CHUNK_SIZE)));
+        for invalid in &["fixed:0", "fixed:65536", "random:1-18446744073709551615", "random:4-2", "random:4", "other"] {
+            assert!(invalid.parse::<Chunking>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn split_random() {
+        let line = [b'x'; 100];
+        for chunking in &[Chunking::Random(3, 7), Chunking::Random(1, MAX_CHUNK_SIZE)] {
+            let chunks = chunking.split(&line);
+            assert_eq!(chunks.iter().map(|chunk| chunk.len()).sum::<usize>(), line.len());
+            if let Chunking::Random(minimum, maximum) = *chunking {
+                assert!(chunks[..chunks.len() - 1].iter().all(|chunk| chunk.len() >= minimum && chunk.len() <= maximum));
+            }
+        }
+    }
+}
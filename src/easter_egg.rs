@@ -0,0 +1,99 @@
+use rand::{
+    Rng,
+    distributions::{Distribution, WeightedIndex},
+};
+use std::fmt;
+
+use super::validation::Validator;
+
+/// A line sent before the banner once in a while.
+pub(crate) struct EasterEgg {
+    pub(crate) id:    String,
+    pub(crate) line:  Vec<u8>,
+}
+
+/// Weighted easter eggs and how often to send one of them.
+pub(crate) struct EasterEggs {
+    eggs:         Vec<EasterEgg>,
+    weights:      Option<WeightedIndex<u32>>,
+    probability:  f64,
+}
+
+impl EasterEggs {
+    /// Parse `file`, one egg per line as `<id> <weight> <text>`, empty lines and lines starting with `#` are skipped.
+    /// Without a file, there is just the cat.
+    pub(crate) fn new(
+        file: Option<&str>,
+        probability: f64,
+        validator: &Validator,
+    ) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(format!("easter eggs, error: \"probability {} not within 0 and 1\"", probability));
+        }
+
+        let (eggs, weights) = match file {
+            None => (
+                vec![EasterEgg { id: "meow".to_owned(), line: b"Meow Meow Meow, but anymeow:\r\n".to_vec() }],
+                vec![1],
+            ),
+            Some(file) => {
+                let mut eggs = Vec::new();
+                let mut weights = Vec::new();
+                for (number, line) in file.lines().enumerate() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let mut fields = line.splitn(3, char::is_whitespace);
+                    let (id, weight, text) = match (fields.next(), fields.next(), fields.next()) {
+                        (Some(id), Some(weight), Some(text)) => (id, weight, text.trim_start()),
+                        _ => return Err(format!("easter eggs, line: {}, error: \"expected <id> <weight> <text>\"", number + 1)),
+                    };
+                    let weight = weight
+                        .parse::<u32>()
+                        .map_err(|err| format!("easter eggs, line: {}, error: \"invalid weight: {}\"", number + 1, err))?;
+                    let text = validator.validate(text.as_bytes())?;
+                    let mut bytes = Vec::with_capacity(text.len() + 2);
+                    for part in text.split('\n') {
+                        bytes.extend_from_slice(part.as_bytes());
+                        bytes.extend_from_slice(b"\r\n");
+                    }
+                    eggs.push(EasterEgg { id: id.to_owned(), line: bytes });
+                    weights.push(weight);
+                }
+                (eggs, weights)
+            },
+        };
+
+        let weights = if probability > 0.0 {
+            Some(
+                WeightedIndex::new(weights)
+                    .map_err(|err| format!("easter eggs, error: \"{}\"", err))?
+            )
+        } else {
+            None
+        };
+        Ok(Self { eggs, weights, probability })
+    }
+
+    /// Maybe choose an egg to send.
+    pub(crate) fn choose(&self) -> Option<&EasterEgg> {
+        let weights = self.weights.as_ref()?;
+        let mut rng = rand::thread_rng();
+        if rng.gen_bool(self.probability) {
+            Some(&self.eggs[weights.sample(&mut rng)])
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for EasterEggs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weights.is_some() {
+            write!(f, "{}, probability: {}", self.eggs.len(), self.probability)
+        } else {
+            f.write_str("off")
+        }
+    }
+}
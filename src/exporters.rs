@@ -31,7 +31,7 @@ impl Exporter {
     ) -> Self {
         Self {
             inner: listen.iter().map(|address| {
-                let listener = runtime.block_on(async { Server::bind(&address) });
+                let listener = runtime.block_on(async { Server::bind(address) });
                 info!("listen, addr: {}", address);
                 listener
             }).collect()
@@ -41,9 +41,8 @@ impl Exporter {
     pub(crate) fn spawn(
         self,
         runtime: &Runtime,
-    ) -> Arc<Metrics> {
-        let metrics = Arc::new(Metrics::new(runtime.start()));
-
+        metrics: &Arc<Metrics>,
+    ) {
         for exporter in self.inner {
             let metrics = metrics.clone();
             runtime.spawn(
@@ -68,8 +67,6 @@ impl Exporter {
                 )
             );
         }
-
-        metrics
     }
 }
 
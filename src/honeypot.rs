@@ -0,0 +1,678 @@
+use log::info;
+use std::{
+    borrow::Cow,
+    fmt,
+    fs,
+    io,
+    net::SocketAddr,
+    path::Path,
+    time::Duration,
+};
+
+use aes::Aes128;
+use ctr::{
+    Ctr128BE,
+    cipher::{NewCipher, StreamCipher},
+};
+use ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer};
+use hmac::{Hmac, Mac, NewMac};
+use rand::{RngCore, rngs::OsRng};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::{delay_for, timeout},
+};
+
+use super::{
+    kex::{self, ClientKexInit},
+    metrics::Metrics,
+};
+
+/// Message numbers, as per RFC 4250.
+const SSH_MSG_DISCONNECT: u8 = 1;
+const SSH_MSG_IGNORE: u8 = 2;
+const SSH_MSG_UNIMPLEMENTED: u8 = 3;
+const SSH_MSG_DEBUG: u8 = 4;
+const SSH_MSG_SERVICE_REQUEST: u8 = 5;
+const SSH_MSG_SERVICE_ACCEPT: u8 = 6;
+const SSH_MSG_KEXINIT: u8 = 20;
+const SSH_MSG_NEWKEYS: u8 = 21;
+const SSH_MSG_KEX_ECDH_INIT: u8 = 30;
+const SSH_MSG_KEX_ECDH_REPLY: u8 = 31;
+const SSH_MSG_USERAUTH_REQUEST: u8 = 50;
+const SSH_MSG_USERAUTH_FAILURE: u8 = 51;
+
+/// The only algorithms offered, supported by every common client.
+const KEX_ALGORITHMS: &[&str] = &["curve25519-sha256", "curve25519-sha256@libssh.org"];
+const HOST_KEY_ALGORITHM: &str = "ssh-ed25519";
+const CIPHER: &str = "aes128-ctr";
+const MAC: &str = "hmac-sha2-256";
+const COMPRESSION: &str = "none";
+
+/// Authentication methods to keep the client trying.
+const METHODS: &str = "publickey,password";
+
+/// Largest packet of the client accepted, as per RFC 4253.
+const MAX_PACKET: usize = 35000;
+
+/// Size of the message authentication code of `hmac-sha2-256`.
+const MAC_LENGTH: usize = 32;
+
+/// Why the session ended and a description.
+pub(crate) type Error = (&'static str, Cow<'static, str>);
+
+fn protocol_error(error: &'static str) -> Error {
+    ("protocol error", Cow::Borrowed(error))
+}
+
+/// Base64 without padding, as used by OpenSSH for fingerprints.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((bytes.len() * 4 + 2) / 3);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (index, &byte)| bits | u32::from(byte) << (16 - 8 * index));
+        for index in 0..=chunk.len() {
+            encoded.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+        }
+    }
+    encoded
+}
+
+/// The OpenSSH-style fingerprint of a public key blob, e.g. `SHA256:uNiVz…`.
+fn fingerprint(blob: &[u8]) -> String {
+    format!("SHA256:{}", base64(&Sha256::digest(blob)))
+}
+
+/// Encode the shared secret as mpint.
+fn mpint(bytes: &[u8]) -> Vec<u8> {
+    let bytes = &bytes[bytes.iter().position(|&byte| byte != 0).unwrap_or(bytes.len())..];
+    let mut encoded = Vec::with_capacity(bytes.len() + 1);
+    if bytes.first().map_or(false, |&byte| byte & 0x80 != 0) {
+        encoded.push(0);
+    }
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+/// Read fields of a payload.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn take(
+        &mut self,
+        length: usize,
+    ) -> Result<&'a [u8], Error> {
+        if self.bytes.len() < length {
+            return Err(protocol_error("truncated message"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn boolean(&mut self) -> Result<bool, Error> {
+        Ok(self.byte()? != 0)
+    }
+
+    fn string(&mut self) -> Result<&'a [u8], Error> {
+        let length = self.take(4)?;
+        let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]);
+        self.take(length as usize)
+    }
+}
+
+/// Keys of one direction after the key exchange.
+struct Keys {
+    cipher: Ctr128BE<Aes128>,
+    mac:    Vec<u8>,
+}
+
+impl Keys {
+    /// Derive the keys, as per RFC 4253, from the shared secret `k`, the exchange hash `h`
+    /// and the letters of the initial IV, the encryption and the integrity key.
+    fn derive(
+        k: &[u8],
+        h: &[u8],
+        letters: [u8; 3],
+    ) -> Self {
+        let derive = |letter: u8| {
+            let mut hasher = Sha256::new();
+            hasher.update(k);
+            hasher.update(h);
+            hasher.update([letter]);
+            hasher.update(h);
+            hasher.finalize()
+        };
+        let iv = derive(letters[0]);
+        let key = derive(letters[1]);
+        Self {
+            cipher: Ctr128BE::<Aes128>::new_from_slices(&key[..16], &iv[..16])
+                .expect("key and iv of aes128-ctr are 16 bytes"),
+            mac:    derive(letters[2]).to_vec(),
+        }
+    }
+
+    fn mac(
+        &self,
+        sequence: u32,
+        packet: &[u8],
+    ) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.mac)
+            .expect("hmac accepts keys of any length");
+        mac.update(&sequence.to_be_bytes());
+        mac.update(packet);
+        mac
+    }
+}
+
+/// The binary packet protocol of a single connection.
+pub(crate) struct Transport {
+    time_out:   Duration,
+    input:      Vec<u8>,
+    incoming:   Option<Keys>,
+    outgoing:   Option<Keys>,
+    received:   u32,
+    sent:       u32,
+}
+
+impl Transport {
+    pub(crate) fn new(time_out: Duration) -> Self {
+        Self {
+            time_out,
+            input:      Vec::new(),
+            incoming:   None,
+            outgoing:   None,
+            received:   0,
+            sent:       0,
+        }
+    }
+
+    /// Continue with the bytes already read after the identification string of the client.
+    pub(crate) fn resume(
+        &mut self,
+        input: Vec<u8>,
+    ) {
+        self.input = input;
+    }
+
+    /// Read until at least `length` bytes are buffered.
+    async fn fill(
+        &mut self,
+        sock: &mut TcpStream,
+        length: usize,
+    ) -> Result<(), Error> {
+        let mut buffer = [0u8; 4096];
+        while self.input.len() < length {
+            match timeout(self.time_out, sock.read(&mut buffer)).await {
+                Err(_) => return Err(("time out", Cow::Borrowed("time out"))),
+                Ok(Ok(0)) => return Err(("eof", Cow::Borrowed("end of file"))),
+                Ok(Ok(read)) => self.input.extend_from_slice(&buffer[..read]),
+                Ok(Err(error)) => return Err((super::tarpit::reason(&error), Cow::Owned(format!("{}", error)))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Length of the first block of an incoming packet.
+    fn block(&self) -> usize {
+        if self.incoming.is_some() { 16 } else { 8 }
+    }
+
+    /// Length of the MAC of an incoming packet.
+    fn mac_length(&self) -> usize {
+        if self.incoming.is_some() { MAC_LENGTH } else { 0 }
+    }
+
+    /// Decrypt the first block of the buffered packet, returns it and the packet length.
+    fn header(&mut self) -> Result<(Vec<u8>, usize), Error> {
+        let block = self.block();
+        let mut packet = self.input[..block].to_vec();
+        if let Some(keys) = &mut self.incoming {
+            keys.cipher.apply_keystream(&mut packet);
+        }
+        let length = u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize;
+        if length > MAX_PACKET || 4 + length < block || (self.incoming.is_some() && (4 + length) % block != 0) {
+            return Err(protocol_error("invalid packet length"));
+        }
+        Ok((packet, length))
+    }
+
+    /// Read the next packet, returns its payload.
+    async fn read_packet(
+        &mut self,
+        sock: &mut TcpStream,
+    ) -> Result<Vec<u8>, Error> {
+        self.fill(sock, self.block()).await?;
+        let (packet, length) = self.header()?;
+        self.fill(sock, 4 + length + self.mac_length()).await?;
+        self.open(packet, length)
+    }
+
+    /// Decrypt and verify the rest of the buffered packet of `length` after its first block `packet`,
+    /// returns its payload.
+    fn open(
+        &mut self,
+        mut packet: Vec<u8>,
+        length: usize,
+    ) -> Result<Vec<u8>, Error> {
+        let block = self.block();
+        let mac_length = self.mac_length();
+        let mut rest = self.input[block..4 + length].to_vec();
+        if let Some(keys) = &mut self.incoming {
+            keys.cipher.apply_keystream(&mut rest);
+            packet.extend_from_slice(&rest);
+            keys.mac(self.received, &packet)
+                .verify(&self.input[4 + length..4 + length + mac_length])
+                .map_err(|_| protocol_error("invalid message authentication code"))?;
+        } else {
+            packet.extend_from_slice(&rest);
+        }
+        self.input.drain(..4 + length + mac_length);
+        self.received = self.received.wrapping_add(1);
+        let padding = packet[4] as usize;
+        if padding + 1 >= length {
+            return Err(protocol_error("invalid padding length"));
+        }
+        Ok(packet[5..4 + length - padding].to_vec())
+    }
+
+    /// Read the next packet, which is not just noise.
+    async fn read_message(
+        &mut self,
+        sock: &mut TcpStream,
+    ) -> Result<Vec<u8>, Error> {
+        loop {
+            let payload = self.read_packet(sock).await?;
+            match payload.first() {
+                None => return Err(protocol_error("empty message")),
+                Some(&SSH_MSG_IGNORE) | Some(&SSH_MSG_DEBUG) | Some(&SSH_MSG_UNIMPLEMENTED) => continue,
+                Some(&SSH_MSG_DISCONNECT) => {
+                    let mut reader = Reader::new(&payload[1..]);
+                    let _code = reader.take(4)?;
+                    let description = String::from_utf8_lossy(reader.string()?).into_owned();
+                    return Err(("disconnect", Cow::Owned(description)));
+                },
+                Some(_) => return Ok(payload),
+            }
+        }
+    }
+
+    /// Send `payload` in a packet.
+    async fn write_packet(
+        &mut self,
+        sock: &mut TcpStream,
+        payload: &[u8],
+    ) -> Result<(), Error> {
+        let packet = self.seal(payload);
+        self.write(sock, &packet).await
+    }
+
+    /// Wrap `payload` into a padded, encrypted and authenticated packet.
+    fn seal(
+        &mut self,
+        payload: &[u8],
+    ) -> Vec<u8> {
+        let block = if self.outgoing.is_some() { 16 } else { 8 };
+        let mut padding = block - (4 + 1 + payload.len()) % block;
+        if padding < 4 {
+            padding += block;
+        }
+        let length = 1 + payload.len() + padding;
+        let mut packet = Vec::with_capacity(4 + length + MAC_LENGTH);
+        packet.extend_from_slice(&(length as u32).to_be_bytes());
+        packet.push(padding as u8);
+        packet.extend_from_slice(payload);
+        packet.extend((0..padding).map(|_| rand::random::<u8>()));
+        if let Some(keys) = &mut self.outgoing {
+            let mac = keys.mac(self.sent, &packet).finalize().into_bytes();
+            keys.cipher.apply_keystream(&mut packet);
+            packet.extend_from_slice(&mac);
+        }
+        self.sent = self.sent.wrapping_add(1);
+        packet
+    }
+
+    /// Send raw `bytes`.
+    pub(crate) async fn write(
+        &self,
+        sock: &mut TcpStream,
+        bytes: &[u8],
+    ) -> Result<(), Error> {
+        match timeout(self.time_out, sock.write_all(bytes)).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(error)) => Err((super::tarpit::reason(&error), Cow::Owned(format!("{}", error)))),
+            Err(_) => Err(("time out", Cow::Borrowed("time out"))),
+        }
+    }
+}
+
+/// A low-interaction SSH server, which records and rejects every login attempt.
+pub(crate) struct Honeypot {
+    after:          usize,
+    reject_delay:   Duration,
+    host_key:       Keypair,
+}
+
+impl Honeypot {
+    /// Load the ed25519 host key, a file with its 32 bytes seed, or generate it, if missing.
+    pub(crate) fn new(
+        after: usize,
+        host_key: &Path,
+        reject_delay: Duration,
+    ) -> Result<Self, String> {
+        let seed = match fs::read(host_key) {
+            Ok(seed) => seed,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                let mut seed = vec![0u8; 32];
+                OsRng.fill_bytes(&mut seed);
+                let mut options = fs::OpenOptions::new();
+                options.write(true).create_new(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                options
+                    .open(host_key)
+                    .and_then(|mut file| io::Write::write_all(&mut file, &seed))
+                    .map_err(|error| format!("host key, file: {}, error: {}", host_key.display(), error))?;
+                info!("host key, file: {}, generated", host_key.display());
+                seed
+            },
+            Err(error) => return Err(format!("host key, file: {}, error: {}", host_key.display(), error)),
+        };
+        let secret = SecretKey::from_bytes(&seed)
+            .map_err(|_| format!("host key, file: {}, error: expected a 32 bytes seed", host_key.display()))?;
+        let public = PublicKey::from(&secret);
+        Ok(Self {
+            after,
+            reject_delay,
+            host_key: Keypair { secret, public },
+        })
+    }
+
+    /// Number of banners to send before.
+    pub(crate) fn after(&self) -> usize {
+        self.after
+    }
+
+    /// The host key as public key blob.
+    fn host_key_blob(&self) -> Vec<u8> {
+        let mut blob = Vec::new();
+        kex::put_string(&mut blob, HOST_KEY_ALGORITHM.as_bytes());
+        kex::put_string(&mut blob, self.host_key.public.as_bytes());
+        blob
+    }
+
+    /// Exchange keys with `curve25519-sha256`, returns the KEXINIT of the client.
+    pub(crate) async fn key_exchange(
+        &self,
+        transport: &mut Transport,
+        sock: &mut TcpStream,
+        client_version: &[u8],
+    ) -> Result<ClientKexInit, Error> {
+        let server_kexinit = kex::kexinit_payload(
+            &KEX_ALGORITHMS.join(","),
+            HOST_KEY_ALGORITHM,
+            CIPHER,
+            MAC,
+            COMPRESSION,
+        );
+        transport.write_packet(sock, &server_kexinit).await?;
+
+        let client_kexinit = transport.read_message(sock).await?;
+        let kexinit = kex::parse(&client_kexinit).map_err(protocol_error)?;
+        let offers = |names: &str, name: &str| names.split(',').any(|offered| offered == name);
+        let algorithm = kexinit.kex.split(',').find(|name| KEX_ALGORITHMS.contains(name));
+        if algorithm.is_none()
+        || !offers(&kexinit.host_key, HOST_KEY_ALGORITHM)
+        || !offers(&kexinit.cipher, CIPHER)
+        || !offers(&kexinit.cipher_server_to_client, CIPHER)
+        || !offers(&kexinit.mac, MAC)
+        || !offers(&kexinit.mac_server_to_client, MAC)
+        || !offers(&kexinit.compression, COMPRESSION) {
+            return Err(("no common algorithm", Cow::Owned(kexinit.hassh_algorithms())));
+        }
+        if kexinit.first_kex_packet_follows
+        && (kexinit.kex.split(',').next() != algorithm
+            || kexinit.host_key.split(',').next() != Some(HOST_KEY_ALGORITHM)) {
+            // The client guessed wrong, ignore its first key exchange packet.
+            transport.read_message(sock).await?;
+        }
+
+        let init = transport.read_message(sock).await?;
+        let mut reader = Reader::new(&init);
+        if reader.byte()? != SSH_MSG_KEX_ECDH_INIT {
+            return Err(protocol_error("expected KEX_ECDH_INIT"));
+        }
+        let client_public = reader.string()?;
+        if client_public.len() != 32 {
+            return Err(protocol_error("invalid curve25519 public key"));
+        }
+        let mut client_key = [0u8; 32];
+        client_key.copy_from_slice(client_public);
+        let secret = x25519_dalek::EphemeralSecret::new(OsRng);
+        let server_public = x25519_dalek::PublicKey::from(&secret);
+        let shared = secret.diffie_hellman(&x25519_dalek::PublicKey::from(client_key));
+        if shared.as_bytes().iter().all(|&byte| byte == 0) {
+            return Err(protocol_error("invalid shared secret"));
+        }
+
+        let mut k = Vec::new();
+        kex::put_string(&mut k, &mpint(shared.as_bytes()));
+        let host_key = self.host_key_blob();
+        let mut hasher = Sha256::new();
+        for part in &[
+            client_version,
+            kex::VERSION.strip_suffix(b"\r\n").unwrap_or(kex::VERSION),
+            &client_kexinit,
+            &server_kexinit,
+            &host_key,
+            client_public,
+            server_public.as_bytes(),
+        ] {
+            let mut string = Vec::with_capacity(4 + part.len());
+            kex::put_string(&mut string, part);
+            hasher.update(&string);
+        }
+        hasher.update(&k);
+        let h = hasher.finalize();
+
+        let mut signature = Vec::new();
+        kex::put_string(&mut signature, HOST_KEY_ALGORITHM.as_bytes());
+        kex::put_string(&mut signature, &self.host_key.sign(&h).to_bytes());
+        let mut reply = vec![SSH_MSG_KEX_ECDH_REPLY];
+        kex::put_string(&mut reply, &host_key);
+        kex::put_string(&mut reply, server_public.as_bytes());
+        kex::put_string(&mut reply, &signature);
+        transport.write_packet(sock, &reply).await?;
+        transport.write_packet(sock, &[SSH_MSG_NEWKEYS]).await?;
+        transport.outgoing = Some(Keys::derive(&k, &h, [b'B', b'D', b'F']));
+
+        if transport.read_message(sock).await?.first() != Some(&SSH_MSG_NEWKEYS) {
+            return Err(protocol_error("expected NEWKEYS"));
+        }
+        transport.incoming = Some(Keys::derive(&k, &h, [b'A', b'C', b'E']));
+        Ok(kexinit)
+    }
+
+    /// Log and slowly reject every authentication request.
+    pub(crate) async fn authenticate(
+        &self,
+        transport: &mut Transport,
+        sock: &mut TcpStream,
+        peer: &SocketAddr,
+        metrics: &Metrics,
+    ) -> Result<(), Error> {
+        loop {
+            let message = transport.read_message(sock).await?;
+            let mut reader = Reader::new(&message);
+            match reader.byte()? {
+                SSH_MSG_SERVICE_REQUEST => {
+                    let service = reader.string()?;
+                    if service != b"ssh-userauth" {
+                        return Err(protocol_error("unknown service"));
+                    }
+                    let mut accept = vec![SSH_MSG_SERVICE_ACCEPT];
+                    kex::put_string(&mut accept, service);
+                    transport.write_packet(sock, &accept).await?;
+                },
+                SSH_MSG_USERAUTH_REQUEST => {
+                    let user = String::from_utf8_lossy(reader.string()?).into_owned();
+                    let _service = reader.string()?;
+                    let method = reader.string()?;
+                    match method {
+                        b"none" => {},
+                        b"password" => {
+                            let _change = reader.boolean()?;
+                            let password = String::from_utf8_lossy(reader.string()?);
+                            info!("login, peer: {}, method: password, user: {:?}, password: {:?}", peer, user, password);
+                            metrics.login_attempt("password");
+                            delay_for(self.reject_delay).await;
+                        },
+                        b"publickey" => {
+                            let _signed = reader.boolean()?;
+                            let algorithm = String::from_utf8_lossy(reader.string()?);
+                            let key = fingerprint(reader.string()?);
+                            info!("login, peer: {}, method: publickey, user: {:?}, algorithm: {:?}, key: {}", peer, user, algorithm, key);
+                            metrics.login_attempt("publickey");
+                            delay_for(self.reject_delay).await;
+                        },
+                        method => {
+                            info!("login, peer: {}, method: {:?}, user: {:?}", peer, String::from_utf8_lossy(method), user);
+                            metrics.login_attempt("other");
+                            delay_for(self.reject_delay).await;
+                        },
+                    }
+                    let mut failure = vec![SSH_MSG_USERAUTH_FAILURE];
+                    kex::put_string(&mut failure, METHODS.as_bytes());
+                    failure.push(0);
+                    transport.write_packet(sock, &failure).await?;
+                },
+                SSH_MSG_KEXINIT => return Err(protocol_error("key re-exchange is not supported")),
+                _ => {
+                    let mut unimplemented = vec![SSH_MSG_UNIMPLEMENTED];
+                    unimplemented.extend_from_slice(&transport.received.wrapping_sub(1).to_be_bytes());
+                    transport.write_packet(sock, &unimplemented).await?;
+                },
+            }
+        }
+    }
+}
+
+impl fmt::Display for Honeypot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "after: {}, reject_delay: {:.2?}, host_key: {}",
+            self.after,
+            self.reject_delay,
+            fingerprint(&self.host_key_blob()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transport() -> Transport {
+        Transport::new(Duration::from_secs(1))
+    }
+
+    /// Read the next packet of the buffered input.
+    fn read(transport: &mut Transport) -> Result<Vec<u8>, Error> {
+        let (packet, length) = transport.header()?;
+        transport.open(packet, length)
+    }
+
+    fn keys() -> Keys {
+        Keys::derive(b"shared secret", b"exchange hash", *b"ACE")
+    }
+
+    #[test]
+    fn base64_without_padding() {
+        for (bytes, encoded) in &[("", ""), ("f", "Zg"), ("fo", "Zm8"), ("foo", "Zm9v"), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64(bytes.as_bytes()), *encoded);
+        }
+    }
+
+    #[test]
+    fn mpints() {
+        assert_eq!(mpint(&[0, 0, 0x7f]), vec![0x7f]);
+        assert_eq!(mpint(&[0, 0x80, 0]), vec![0, 0x80, 0]);
+        assert_eq!(mpint(&[0, 0]), Vec::<u8>::new());
+    }
+
+    #[test]
+    fn reader() {
+        let mut reader = Reader::new(&[0, 0, 0, 2, b'h', b'i', 1]);
+        assert_eq!(reader.string().unwrap(), b"hi");
+        assert!(reader.boolean().unwrap());
+        assert!(reader.byte().is_err());
+        assert!(Reader::new(&[0xff, 0xff, 0xff, 0xff, 0]).string().is_err());
+        assert!(Reader::new(&[0, 0, 0]).string().is_err());
+    }
+
+    #[test]
+    fn plain_packets() {
+        let mut sender = transport();
+        let mut receiver = transport();
+        let mut input = sender.seal(b"hello");
+        assert_eq!(input.len() % 8, 0);
+        input.extend_from_slice(&sender.seal(&[SSH_MSG_IGNORE]));
+        receiver.resume(input);
+        assert_eq!(read(&mut receiver).unwrap(), b"hello");
+        assert_eq!(read(&mut receiver).unwrap(), vec![SSH_MSG_IGNORE]);
+        assert!(receiver.input.is_empty());
+    }
+
+    #[test]
+    fn encrypted_packets() {
+        let mut sender = transport();
+        let mut receiver = transport();
+        sender.outgoing = Some(keys());
+        receiver.incoming = Some(keys());
+        for payload in &[&b"first"[..], &[0u8; 100][..], &[SSH_MSG_USERAUTH_REQUEST]] {
+            let packet = sender.seal(payload);
+            assert_eq!((packet.len() - MAC_LENGTH) % 16, 0);
+            receiver.resume(packet);
+            assert_eq!(read(&mut receiver).unwrap(), *payload);
+        }
+    }
+
+    #[test]
+    fn tampered_packets() {
+        let mut sender = transport();
+        let mut receiver = transport();
+        sender.outgoing = Some(keys());
+        receiver.incoming = Some(keys());
+        let mut packet = sender.seal(b"password");
+        let last = packet.len() - 1;
+        packet[last] ^= 1;
+        receiver.resume(packet);
+        assert_eq!(read(&mut receiver).unwrap_err().1, "invalid message authentication code");
+    }
+
+    #[test]
+    fn invalid_packets() {
+        let mut receiver = transport();
+        receiver.resume(vec![0, 1, 0, 0, 4, 0, 0, 0]);
+        assert_eq!(receiver.header().unwrap_err().1, "invalid packet length");
+        receiver.resume(vec![0, 0, 0, 3, 1, 0, 0, 0]);
+        assert_eq!(receiver.header().unwrap_err().1, "invalid packet length");
+        receiver.resume(vec![0, 0, 0, 8, 7, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(read(&mut receiver).unwrap_err().1, "invalid padding length");
+    }
+}
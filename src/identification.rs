@@ -0,0 +1,97 @@
+/// Longest identification string to capture, as per RFC 4253 including CRLF.
+const MAX_LENGTH: usize = 255;
+
+/// Known client software, by the prefix of the softwareversion, ignoring case.
+/// More specific prefixes must come first.
+const SIGNATURES: &[(&str, &str)] = &[
+    ("OpenSSH",           "openssh"),
+    ("libssh2",           "libssh2"),
+    ("libssh",            "libssh"),
+    ("paramiko",          "paramiko"),
+    ("Go",                "go"),
+    ("ZGrab",             "zgrab"),
+    ("PuTTY",             "putty"),
+    ("dropbear",          "dropbear"),
+    ("JSCH",              "jsch"),
+    ("AsyncSSH",          "asyncssh"),
+    ("Twisted",           "twisted"),
+    ("Ruby/Net::SSH",     "net-ssh"),
+    ("ssh2js",            "ssh2js"),
+    ("russh",             "russh"),
+    ("thrussh",           "thrussh"),
+    ("Nmap",              "nmap"),
+    ("WinSCP",            "winscp"),
+    ("Bitvise",           "bitvise"),
+    ("SecureCRT",         "securecrt"),
+    ("MobaXterm",         "mobaxterm"),
+    ("Erlang",            "erlang"),
+    ("Cisco",             "cisco"),
+];
+
+/// The first line sent by the client, usually its SSH identification string.
+pub(crate) struct Identification {
+    line:   Vec<u8>,
+    done:   bool,
+}
+
+impl Identification {
+    pub(crate) fn new() -> Self {
+        Self {
+            line: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// The captured line, lossily decoded, once complete.
+    pub(crate) fn version(&self) -> Option<String> {
+        if self.done {
+            Some(String::from_utf8_lossy(&self.line).into_owned())
+        } else {
+            None
+        }
+    }
+
+    /// Feed bytes read from the client, returns the class once the first line is complete
+    /// and the bytes following the first line.
+    pub(crate) fn feed<'a>(
+        &mut self,
+        bytes: &'a [u8],
+    ) -> (Option<&'static str>, &'a [u8]) {
+        if self.done {
+            return (None, bytes);
+        }
+        let (line, rest, complete) = match bytes.iter().position(|&byte| byte == b'\n') {
+            Some(end) => (&bytes[..end], &bytes[end + 1..], true),
+            None => (bytes, &bytes[bytes.len()..], false),
+        };
+        let room = MAX_LENGTH.saturating_sub(self.line.len());
+        self.line.extend_from_slice(&line[..line.len().min(room)]);
+        if complete || self.line.len() >= MAX_LENGTH {
+            if self.line.ends_with(b"\r") {
+                self.line.pop();
+            }
+            self.done = true;
+            (Some(classify(&self.line)), rest)
+        } else {
+            (None, rest)
+        }
+    }
+}
+
+/// Classify a client by its identification string `SSH-protoversion-softwareversion comments`.
+pub(crate) fn classify(line: &[u8]) -> &'static str {
+    if !line.starts_with(b"SSH-") {
+        return "non-ssh";
+    }
+    let software = line
+        .splitn(3, |&byte| byte == b'-')
+        .nth(2)
+        .unwrap_or(b"");
+    SIGNATURES
+        .iter()
+        .find(|(prefix, _)| {
+            software.len() >= prefix.len()
+            && software[..prefix.len()].eq_ignore_ascii_case(prefix.as_bytes())
+        })
+        .map_or("unknown", |(_, class)| class)
+}
@@ -0,0 +1,277 @@
+use rand::Rng;
+
+/// The version string sent when switching to the key exchange.
+pub(crate) const VERSION: &[u8] = b"SSH-2.0-OpenSSH_8.9p1\r\n";
+
+/// Message numbers, as per RFC 4253.
+const SSH_MSG_IGNORE: u8 = 2;
+const SSH_MSG_KEXINIT: u8 = 20;
+
+/// Largest packet of the client accepted, as per RFC 4253.
+const MAX_PACKET: usize = 35000;
+
+/// Algorithms offered in our KEXINIT.
+const KEX_ALGORITHMS: &str = "curve25519-sha256,curve25519-sha256@libssh.org,ecdh-sha2-nistp256,ecdh-sha2-nistp384,diffie-hellman-group16-sha512,diffie-hellman-group14-sha256";
+const HOST_KEY_ALGORITHMS: &str = "rsa-sha2-512,rsa-sha2-256,ecdsa-sha2-nistp256,ssh-ed25519";
+const CIPHERS: &str = "chacha20-poly1305@openssh.com,aes128-ctr,aes192-ctr,aes256-ctr,aes128-gcm@openssh.com,aes256-gcm@openssh.com";
+const MACS: &str = "umac-64-etm@openssh.com,umac-128-etm@openssh.com,hmac-sha2-256-etm@openssh.com,hmac-sha2-512-etm@openssh.com,hmac-sha2-256,hmac-sha2-512";
+const COMPRESSION: &str = "none,zlib@openssh.com";
+
+/// Wrap `payload` into an unencrypted binary packet with random padding.
+fn packet(payload: &[u8]) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut padding = 8 - (4 + 1 + payload.len()) % 8;
+    if padding < 4 {
+        padding += 8;
+    }
+    let length = 1 + payload.len() + padding;
+    let mut packet = Vec::with_capacity(4 + length);
+    packet.extend_from_slice(&(length as u32).to_be_bytes());
+    packet.push(padding as u8);
+    packet.extend_from_slice(payload);
+    packet.extend((0..padding).map(|_| rng.gen::<u8>()));
+    packet
+}
+
+/// Append `bytes` as a length-prefixed string or name-list.
+pub(crate) fn put_string(
+    payload: &mut Vec<u8>,
+    bytes: &[u8],
+) {
+    payload.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    payload.extend_from_slice(bytes);
+}
+
+/// The payload of a SSH_MSG_KEXINIT with a random cookie, offering the same algorithms in both directions.
+pub(crate) fn kexinit_payload(
+    kex: &str,
+    host_key: &str,
+    cipher: &str,
+    mac: &str,
+    compression: &str,
+) -> Vec<u8> {
+    let mut payload = vec![SSH_MSG_KEXINIT];
+    payload.extend((0..16).map(|_| rand::random::<u8>()));
+    for names in &[kex, host_key, cipher, cipher, mac, mac, compression, compression, "", ""] {
+        put_string(&mut payload, names.as_bytes());
+    }
+    payload.push(0);
+    payload.extend_from_slice(&[0, 0, 0, 0]);
+    payload
+}
+
+/// A valid SSH_MSG_KEXINIT packet with a random cookie.
+pub(crate) fn kexinit() -> Vec<u8> {
+    packet(&kexinit_payload(KEX_ALGORITHMS, HOST_KEY_ALGORITHMS, CIPHERS, MACS, COMPRESSION))
+}
+
+/// A SSH_MSG_IGNORE packet with some random data, to keep waiting for the key exchange.
+pub(crate) fn ignore() -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let data: Vec<u8> = (0..rng.gen_range(4, 32)).map(|_| rng.gen::<u8>()).collect();
+    let mut payload = vec![SSH_MSG_IGNORE];
+    payload.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    payload.extend_from_slice(&data);
+    packet(&payload)
+}
+
+/// The algorithms offered by the client, client to server unless noted otherwise.
+pub(crate) struct ClientKexInit {
+    pub(crate) kex:                         String,
+    pub(crate) host_key:                    String,
+    pub(crate) cipher:                      String,
+    #[cfg(feature = "honeypot")]
+    pub(crate) cipher_server_to_client:     String,
+    pub(crate) mac:                         String,
+    #[cfg(feature = "honeypot")]
+    pub(crate) mac_server_to_client:        String,
+    pub(crate) compression:                 String,
+    #[cfg(feature = "honeypot")]
+    pub(crate) first_kex_packet_follows:    bool,
+}
+
+impl ClientKexInit {
+    /// The HASSH-string `kex;cipher;mac;compression`.
+    pub(crate) fn hassh_algorithms(&self) -> String {
+        format!("{};{};{};{}", self.kex, self.cipher, self.mac, self.compression)
+    }
+
+    /// The HASSH-fingerprint, the MD5-hash of the HASSH-string.
+    pub(crate) fn hassh(&self) -> String {
+        format!("{:x}", md5::compute(self.hassh_algorithms()))
+    }
+}
+
+/// Collects the bytes following the identification string and parses the KEXINIT of the client.
+pub(crate) struct KexInitParser {
+    buffer: Vec<u8>,
+    done:   bool,
+}
+
+impl KexInitParser {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            done:   false,
+        }
+    }
+
+    /// Feed bytes read after the identification string, returns the KEXINIT once complete.
+    pub(crate) fn feed(
+        &mut self,
+        bytes: &[u8],
+    ) -> Option<Result<ClientKexInit, &'static str>> {
+        if bytes.is_empty() {
+            return None;
+        }
+        if self.buffer.len() < 2 * MAX_PACKET {
+            self.buffer.extend_from_slice(bytes);
+        }
+        if self.done || self.buffer.len() < 4 {
+            return None;
+        }
+        let length = u32::from_be_bytes([self.buffer[0], self.buffer[1], self.buffer[2], self.buffer[3]]) as usize;
+        if !(2..=MAX_PACKET).contains(&length) {
+            self.done = true;
+            return Some(Err("invalid packet length"));
+        }
+        if self.buffer.len() < 4 + length {
+            return None;
+        }
+        self.done = true;
+        let padding = self.buffer[4] as usize;
+        if padding + 1 > length {
+            return Some(Err("invalid padding length"));
+        }
+        Some(parse(&self.buffer[5..4 + length - padding]))
+    }
+
+    /// Take the bytes collected so far and whether the KEXINIT was already reported.
+    #[cfg(feature = "honeypot")]
+    pub(crate) fn take(&mut self) -> (Vec<u8>, bool) {
+        (std::mem::take(&mut self.buffer), self.done)
+    }
+}
+
+/// Parse the payload of a SSH_MSG_KEXINIT.
+pub(crate) fn parse(payload: &[u8]) -> Result<ClientKexInit, &'static str> {
+    if payload.first() != Some(&SSH_MSG_KEXINIT) {
+        return Err("not a KEXINIT");
+    }
+    let mut rest = payload.get(17..).ok_or("truncated KEXINIT")?;
+    let mut next = || -> Result<String, &'static str> {
+        if rest.len() < 4 {
+            return Err("truncated KEXINIT");
+        }
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let names = 4usize
+            .checked_add(length)
+            .and_then(|end| rest.get(4..end))
+            .ok_or("truncated KEXINIT")?;
+        rest = &rest[4 + length..];
+        Ok(String::from_utf8_lossy(names).into_owned())
+    };
+    let kex = next()?;
+    let host_key = next()?;
+    let cipher = next()?;
+    #[cfg_attr(not(feature = "honeypot"), allow(unused_variables))]
+    let cipher_server_to_client = next()?;
+    let mac = next()?;
+    #[cfg_attr(not(feature = "honeypot"), allow(unused_variables))]
+    let mac_server_to_client = next()?;
+    let compression = next()?;
+    let _compression_server_to_client = next()?;
+    let _languages_client_to_server = next()?;
+    let _languages_server_to_client = next()?;
+    Ok(ClientKexInit {
+        kex,
+        host_key,
+        cipher,
+        #[cfg(feature = "honeypot")]
+        cipher_server_to_client,
+        mac,
+        #[cfg(feature = "honeypot")]
+        mac_server_to_client,
+        compression,
+        #[cfg(feature = "honeypot")]
+        first_kex_packet_follows: rest.first().map_or(false, |&follows| follows != 0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_kexinit() -> Vec<u8> {
+        let mut payload = kexinit_payload("curve25519-sha256,kex-strict-c-v00@openssh.com", "ssh-ed25519", "aes128-ctr", "hmac-sha2-256", "none");
+        let last = payload.len() - 5;
+        payload[last] = 1;
+        payload
+    }
+
+    #[test]
+    fn packets() {
+        for length in 0..64 {
+            let packet = packet(&vec![0u8; length]);
+            let padding = packet[4] as usize;
+            assert_eq!(packet.len() % 8, 0);
+            assert!((4..4 + 8).contains(&padding));
+            assert_eq!(u32::from_be_bytes([packet[0], packet[1], packet[2], packet[3]]) as usize, packet.len() - 4);
+        }
+    }
+
+    #[test]
+    fn parse_kexinit() {
+        let kexinit = parse(&client_kexinit()).unwrap();
+        assert_eq!(kexinit.kex, "curve25519-sha256,kex-strict-c-v00@openssh.com");
+        assert_eq!(kexinit.host_key, "ssh-ed25519");
+        assert_eq!(kexinit.cipher, "aes128-ctr");
+        #[cfg(feature = "honeypot")]
+        {
+            assert_eq!(kexinit.cipher_server_to_client, "aes128-ctr");
+            assert_eq!(kexinit.mac_server_to_client, "hmac-sha2-256");
+            assert!(kexinit.first_kex_packet_follows);
+        }
+        assert_eq!(kexinit.hassh_algorithms(), "curve25519-sha256,kex-strict-c-v00@openssh.com;aes128-ctr;hmac-sha2-256;none");
+        assert_eq!(kexinit.hassh().len(), 32);
+    }
+
+    #[test]
+    fn invalid_kexinit() {
+        let payload = client_kexinit();
+        assert_eq!(parse(&payload[1..]).err(), Some("not a KEXINIT"));
+        assert_eq!(parse(&payload[..10]).err(), Some("truncated KEXINIT"));
+        assert_eq!(parse(&payload[..payload.len() - 10]).err(), Some("truncated KEXINIT"));
+        let mut payload = payload;
+        payload[17..21].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(parse(&payload).err(), Some("truncated KEXINIT"));
+    }
+
+    #[test]
+    fn feed_fragments() {
+        let packet = packet(&client_kexinit());
+        let mut parser = KexInitParser::new();
+        for byte in &packet[..packet.len() - 1] {
+            assert!(parser.feed(&[*byte]).is_none());
+        }
+        assert!(matches!(parser.feed(&packet[packet.len() - 1..]), Some(Ok(_))));
+        assert!(parser.feed(b"more").is_none());
+        #[cfg(feature = "honeypot")]
+        {
+            let (buffer, reported) = parser.take();
+            assert_eq!(buffer.len(), packet.len() + 4);
+            assert!(reported);
+        }
+    }
+
+    #[test]
+    fn feed_invalid_packets() {
+        let mut parser = KexInitParser::new();
+        assert_eq!(parser.feed(&[0, 0, 0xff, 0xff]).map(|result| result.err()), Some(Some("invalid packet length")));
+        let mut parser = KexInitParser::new();
+        assert_eq!(parser.feed(&[0, 0, 0, 4, 4, 0, 0, 0]).map(|result| result.err()), Some(Some("invalid padding length")));
+        let mut parser = KexInitParser::new();
+        assert!(parser.feed(&[]).is_none());
+        assert!(parser.feed(&[0, 0, 0]).is_none());
+    }
+}
@@ -0,0 +1,293 @@
+use log::{debug, info, warn};
+use std::{
+    collections::HashMap,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::TcpListener,
+    time::delay_for,
+};
+
+use super::{
+    address,
+    errx,
+    runtime::Runtime,
+};
+
+/// Upper bound of remembered clients, knocking or trusted.
+const MAX_ENTRIES: usize = 65536;
+
+/// How far a client got through the knock sequence.
+struct Progress {
+    knocked:  usize,
+    started:  Instant,
+}
+
+#[derive(Default)]
+struct State {
+    progress: HashMap<IpAddr, Progress>,
+    /// Trusted clients and when their trust expires.
+    trusted:  HashMap<IpAddr, Instant>,
+}
+
+/// Trusts clients for a while, after they connected to a sequence of ports in order.
+/// Connections to decoy ports or the tarpit in between start over.
+/// The ports are never logged, only the progress through them.
+pub(crate) struct Knocks {
+    /// Listeners by their index in the sequence, `None` for decoys.
+    listeners:  Mutex<Vec<(Option<usize>, TcpListener)>>,
+    length:     usize,
+    decoys:     usize,
+    window:     Duration,
+    ttl:        Duration,
+    state:      Mutex<State>,
+}
+
+impl Knocks {
+    /// Listen on the `ports` of the knock sequence and the `decoys` at `address`.
+    pub(crate) fn new(
+        runtime: &mut Runtime,
+        address: IpAddr,
+        ports: &[u16],
+        decoys: &[u16],
+        window: Duration,
+        ttl: Duration,
+    ) -> Self {
+        let mut bind = |port: u16, name: &str, index: usize| runtime
+            .block_on(async { TcpListener::bind(SocketAddr::new(address, port)).await })
+            .unwrap_or_else(|err| errx(exitcode::OSERR, format!("knock, {}: {}, error: {}", name, index + 1, err)));
+        let mut listeners: Vec<_> = ports
+            .iter()
+            .enumerate()
+            .map(|(index, port)| (Some(index), bind(*port, "knock", index)))
+            .collect();
+        listeners.extend(decoys.iter().enumerate().map(|(index, port)| (None, bind(*port, "decoy", index))));
+        Self {
+            listeners:  Mutex::new(listeners),
+            length:     ports.len(),
+            decoys:     decoys.len(),
+            window,
+            ttl,
+            state:      Mutex::new(State::default()),
+        }
+    }
+
+    /// Accept the knocks and the connections to decoys and close them right away.
+    pub(crate) fn spawn(
+        self: &Arc<Self>,
+        runtime: &Runtime,
+    ) {
+        let listeners = match self.listeners.lock() {
+            Ok(mut guard) => std::mem::take(&mut *guard),
+            Err(poisoned) => std::mem::take(&mut *poisoned.into_inner()),
+        };
+        for (index, mut listener) in listeners {
+            let knocks = self.clone();
+            runtime.spawn(async move {
+                loop {
+                    match listener.accept().await {
+                        Ok((sock, peer)) => {
+                            drop(sock);
+                            match index {
+                                Some(index) => knocks.knocked(peer.ip(), index),
+                                None => knocks.interrupted(peer.ip()),
+                            }
+                        },
+                        Err(err) => {
+                            let wait = Duration::from_millis(100);
+                            match index {
+                                Some(index) => warn!("knock, knock: {}, error: {}, wait: {:?}", index + 1, err, wait),
+                                None => warn!("knock, decoy, error: {}, wait: {:?}", err, wait),
+                            }
+                            delay_for(wait).await;
+                        },
+                    }
+                }
+            });
+        }
+    }
+
+    /// The client at `ip` knocked at the port `index` of the sequence.
+    fn knocked(
+        &self,
+        ip: IpAddr,
+        index: usize,
+    ) {
+        let ip = address::network(ip, 32, 128).0;
+        let now = Instant::now();
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if state.progress.len() >= MAX_ENTRIES {
+            let window = self.window;
+            state.progress.retain(|_, progress| now.duration_since(progress.started) < window);
+        }
+        let knocked = match state.progress.remove(&ip) {
+            Some(progress) if progress.knocked == index && now.duration_since(progress.started) < self.window => {
+                Some(Progress { knocked: index + 1, started: progress.started })
+            },
+            _ if index == 0 => Some(Progress { knocked: 1, started: now }),
+            Some(progress) => {
+                debug!("knock, peer: {}, progress: {}/{}, reset", ip, progress.knocked, self.length);
+                None
+            },
+            None => None,
+        };
+        match knocked {
+            Some(progress) if progress.knocked == self.length => {
+                if state.trusted.len() >= MAX_ENTRIES {
+                    state.trusted.retain(|_, expiry| *expiry > now);
+                }
+                match now.checked_add(self.ttl) {
+                    Some(expiry) if state.trusted.len() < MAX_ENTRIES || state.trusted.contains_key(&ip) => {
+                        state.trusted.insert(ip, expiry);
+                        info!("knock, peer: {}, trusted: {:.2?}", ip, self.ttl);
+                    },
+                    Some(_) => warn!("knock, peer: {}, error: \"too many trusted clients\"", ip),
+                    None => warn!("knock, peer: {}, error: \"invalid ttl\"", ip),
+                }
+            },
+            Some(progress) if state.progress.len() < MAX_ENTRIES => {
+                info!("knock, peer: {}, progress: {}/{}", ip, progress.knocked, self.length);
+                state.progress.insert(ip, progress);
+            },
+            Some(_) => warn!("knock, peer: {}, error: \"too many clients knocking\"", ip),
+            None => (),
+        }
+    }
+
+    /// The client at `ip` connected somewhere else than the next port of the sequence, e.g. to the tarpit.
+    pub(crate) fn interrupted(
+        &self,
+        ip: IpAddr,
+    ) {
+        let ip = address::network(ip, 32, 128).0;
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(progress) = state.progress.remove(&ip) {
+            debug!("knock, peer: {}, progress: {}/{}, reset", ip, progress.knocked, self.length);
+        }
+    }
+
+    /// Whether the client at `ip` completed the knock sequence within the TTL.
+    pub(crate) fn trusts(
+        &self,
+        ip: IpAddr,
+    ) -> bool {
+        let ip = address::network(ip, 32, 128).0;
+        let mut state = match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match state.trusted.get(&ip) {
+            Some(expiry) if *expiry > Instant::now() => true,
+            Some(_) => {
+                state.trusted.remove(&ip);
+                false
+            },
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for Knocks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "knocks: {}, decoys: {}, window: {:.2?}, ttl: {:.2?}", self.length, self.decoys, self.window, self.ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn knocks(length: usize, window: Duration) -> Knocks {
+        Knocks {
+            listeners:  Mutex::new(Vec::new()),
+            length,
+            decoys:     0,
+            window,
+            ttl:        Duration::from_secs(3600),
+            state:      Mutex::new(State::default()),
+        }
+    }
+
+    fn peer() -> IpAddr {
+        "192.0.2.1".parse().unwrap()
+    }
+
+    #[test]
+    fn sequence_in_order() {
+        let knocks = knocks(3, Duration::from_secs(10));
+        for index in 0..3 {
+            assert!(!knocks.trusts(peer()));
+            knocks.knocked(peer(), index);
+        }
+        assert!(knocks.trusts(peer()));
+        assert!(!knocks.trusts("192.0.2.2".parse().unwrap()));
+        assert!(knocks.state.lock().unwrap().progress.is_empty());
+    }
+
+    #[test]
+    fn sequence_out_of_order() {
+        let knocks = knocks(3, Duration::from_secs(10));
+        knocks.knocked(peer(), 0);
+        knocks.knocked(peer(), 2);
+        knocks.knocked(peer(), 1);
+        knocks.knocked(peer(), 2);
+        assert!(!knocks.trusts(peer()));
+        knocks.knocked(peer(), 1);
+        knocks.knocked(peer(), 0);
+        knocks.knocked(peer(), 0);
+        knocks.knocked(peer(), 1);
+        knocks.knocked(peer(), 2);
+        assert!(knocks.trusts(peer()));
+    }
+
+    #[test]
+    fn interrupted_sequence() {
+        let knocks = knocks(2, Duration::from_secs(10));
+        knocks.knocked(peer(), 0);
+        knocks.interrupted(peer());
+        knocks.knocked(peer(), 1);
+        assert!(!knocks.trusts(peer()));
+        knocks.knocked(peer(), 0);
+        knocks.interrupted("192.0.2.2".parse().unwrap());
+        knocks.knocked(peer(), 1);
+        assert!(knocks.trusts(peer()));
+    }
+
+    #[test]
+    fn expired_window_and_ttl() {
+        let knocks = knocks(2, Duration::from_secs(0));
+        knocks.knocked(peer(), 0);
+        knocks.knocked(peer(), 1);
+        assert!(!knocks.trusts(peer()));
+        let knocks = Knocks { ttl: Duration::from_secs(0), ..self::knocks(1, Duration::from_secs(10)) };
+        knocks.knocked(peer(), 0);
+        assert!(!knocks.trusts(peer()));
+        assert!(knocks.state.lock().unwrap().trusted.is_empty());
+    }
+
+    #[test]
+    fn bounded_entries() {
+        let knocks = knocks(2, Duration::from_secs(10));
+        let address = |n: usize| IpAddr::from([10, (n >> 16) as u8, (n >> 8) as u8, n as u8]);
+        for n in 0..MAX_ENTRIES + 1 {
+            knocks.knocked(address(n), 0);
+        }
+        assert_eq!(knocks.state.lock().unwrap().progress.len(), MAX_ENTRIES);
+        let now = Instant::now();
+        knocks.state.lock().unwrap().trusted = (0..MAX_ENTRIES).map(|n| (address(n), now + knocks.ttl)).collect();
+        knocks.state.lock().unwrap().progress.clear();
+        knocks.knocked(address(MAX_ENTRIES), 0);
+        knocks.knocked(address(MAX_ENTRIES), 1);
+        assert!(!knocks.trusts(address(MAX_ENTRIES)));
+        assert_eq!(knocks.state.lock().unwrap().trusted.len(), MAX_ENTRIES);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    address,
+    overload::Overload,
+};
+
+/// Connections of a single source, counted per address and per network.
+#[derive(Default)]
+struct Counts {
+    ips:      HashMap<IpAddr, usize>,
+    prefixes: HashMap<(IpAddr, u8), usize>,
+}
+
+/// Limits of concurrent connections, in total and per source.
+pub(crate) struct Limits {
+    pub(crate) max_clients:     usize,
+    pub(crate) overload:        Overload,
+    pub(crate) max_per_ip:      Option<usize>,
+    pub(crate) max_per_prefix:  Option<usize>,
+    pub(crate) ipv4_bits:       u8,
+    pub(crate) ipv6_bits:       u8,
+    counts:                     Mutex<Counts>,
+}
+
+impl Limits {
+    pub(crate) fn new(
+        max_clients: usize,
+        overload: Overload,
+        max_per_ip: Option<usize>,
+        max_per_prefix: Option<usize>,
+        ipv4_bits: u8,
+        ipv6_bits: u8,
+    ) -> Self {
+        Self {
+            max_clients,
+            overload,
+            max_per_ip,
+            max_per_prefix,
+            ipv4_bits,
+            ipv6_bits,
+            counts: Mutex::new(Counts::default()),
+        }
+    }
+
+    /// The address and the network of `ip`, with IPv4-mapped addresses as IPv4.
+    fn keys(
+        &self,
+        ip: IpAddr,
+    ) -> (IpAddr, (IpAddr, u8)) {
+        (address::network(ip, 32, 128).0, address::network(ip, self.ipv4_bits, self.ipv6_bits))
+    }
+
+    /// Count a new connection of `ip`, unless it exceeds a limit per source, returns the reason otherwise.
+    /// The connection is counted, until the returned guard is dropped.
+    pub(crate) fn acquire(
+        self: &Arc<Self>,
+        ip: IpAddr,
+    ) -> Result<SourceGuard, &'static str> {
+        if self.max_per_ip.is_none() && self.max_per_prefix.is_none() {
+            return Ok(SourceGuard { limits: None, ip });
+        }
+        let (ip_key, prefix_key) = self.keys(ip);
+        let mut counts = match self.counts.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if self.max_per_ip.map_or(false, |max| counts.ips.get(&ip_key).copied().unwrap_or(0) >= max) {
+            return Err("max per ip");
+        }
+        if self.max_per_prefix.map_or(false, |max| counts.prefixes.get(&prefix_key).copied().unwrap_or(0) >= max) {
+            return Err("max per prefix");
+        }
+        *counts.ips.entry(ip_key).or_insert(0) += 1;
+        *counts.prefixes.entry(prefix_key).or_insert(0) += 1;
+        Ok(SourceGuard { limits: Some(self.clone()), ip })
+    }
+
+    fn release(
+        &self,
+        ip: IpAddr,
+    ) {
+        let (ip_key, prefix_key) = self.keys(ip);
+        let mut counts = match self.counts.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(count) = counts.ips.get_mut(&ip_key) {
+            *count -= 1;
+            if *count == 0 {
+                counts.ips.remove(&ip_key);
+            }
+        }
+        if let Some(count) = counts.prefixes.get_mut(&prefix_key) {
+            *count -= 1;
+            if *count == 0 {
+                counts.prefixes.remove(&prefix_key);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "max_clients: {}, overload: {}, max_per_ip: {:?}, max_per_prefix: {:?}, prefixes: /{} /{}",
+            self.max_clients,
+            self.overload,
+            self.max_per_ip,
+            self.max_per_prefix,
+            self.ipv4_bits,
+            self.ipv6_bits,
+        )
+    }
+}
+
+/// A connection counted against the limits per source.
+pub(crate) struct SourceGuard {
+    limits: Option<Arc<Limits>>,
+    ip:     IpAddr,
+}
+
+impl Drop for SourceGuard {
+    fn drop(&mut self) {
+        if let Some(limits) = &self.limits {
+            limits.release(self.ip);
+        }
+    }
+}
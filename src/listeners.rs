@@ -1,13 +1,19 @@
 use log::{info, warn};
 use std::{
-    net::SocketAddr,
     sync::Arc,
     time::{Duration, Instant},
 };
 use super::{
+    access_list::AccessLists,
+    activation::{self, ListenFd},
     errx,
-    tarpit::tarpit_connection,
+    knock::Knocks,
+    protocol::{ListenAddress, Protocol},
+    tarpit::{tarpit_connection, Tarpit},
+    limits::Limits,
     metrics::Metrics,
+    proxy::Proxy,
+    reject::Reject,
     runtime::Runtime,
 };
 use tokio::{
@@ -16,33 +22,59 @@ use tokio::{
 };
 
 pub(crate) struct Listeners {
-    inner: Vec<TcpListener>,
+    /// Listeners with their names, e.g. `0.0.0.0:2222` or the name of an inherited socket.
+    inner:  Vec<(String, Protocol, TcpListener)>,
+    access: Arc<AccessLists>,
+    proxy:  Option<Arc<Proxy>>,
+    knocks: Option<Arc<Knocks>>,
 }
 
 impl Listeners {
     pub(crate) fn new(
         runtime: &mut Runtime,
-        listen: Vec<SocketAddr>,
+        listen: Vec<ListenAddress>,
+        inherited: Vec<(String, ListenFd)>,
+        access: Arc<AccessLists>,
+        proxy: Option<Arc<Proxy>>,
+        knocks: Option<Arc<Knocks>>,
     ) -> Self {
+        let mut inner: Vec<_> = listen
+            .iter()
+            .map(
+                |addr| match runtime.block_on(async { TcpListener::bind(addr.address).await }) {
+                    Ok(listener) => {
+                        info!("listen, name: {}, addr: {}, protocol: {}", addr.address, addr.address, addr.protocol);
+                        (addr.address.to_string(), addr.protocol, listener)
+                    }
+                    Err(err) => {
+                        errx(
+                            exitcode::OSERR,
+                            format!("listen, addr: {}, error: {}", addr.address, err),
+                        );
+                    }
+                },
+            )
+            .collect();
+        inner.extend(inherited.into_iter().map(|(name, listen_fd)| {
+            let listener = activation::listener(listen_fd.fd)
+                .and_then(|listener| runtime
+                    .enter(|| TcpListener::from_std(listener))
+                    .map_err(|err| format!("listen, fd: {}, error: {}", listen_fd.fd, err)))
+                .unwrap_or_else(|err| errx(exitcode::OSERR, format!("{}, name: {}", err, name)));
+            info!(
+                "listen, name: {}, addr: {}, protocol: {}, fd: {}",
+                name,
+                listener.local_addr().map_or_else(|err| err.to_string(), |addr| addr.to_string()),
+                listen_fd.protocol,
+                listen_fd.fd,
+            );
+            (name, listen_fd.protocol, listener)
+        }));
         Self {
-            inner:
-                listen
-                .iter()
-                .map(
-                    |addr| match runtime.block_on(async { TcpListener::bind(addr).await }) {
-                        Ok(listener) => {
-                            info!("listen, addr: {}", addr);
-                            listener
-                        }
-                        Err(err) => {
-                            errx(
-                                exitcode::OSERR,
-                                format!("listen, addr: {}, error: {}", addr, err),
-                            );
-                        }
-                    },
-                )
-                .collect()
+            inner,
+            access,
+            proxy,
+            knocks,
         }
     }
 
@@ -55,45 +87,87 @@ impl Listeners {
     pub(crate) fn spawn(
         self,
         runtime: &Runtime,
-        max_clients: usize,
-        delay: Duration,
-        timeout: Duration,
+        limits: Limits,
+        reject: Reject,
         metrics: Arc<Metrics>,
-        banner: String,
+        tarpit: Tarpit,
     ) {
         info!(
-            "start, servers: {}, max_clients: {}, delay: {}s, timeout: {}s, banner:\n{}",
+            "start, servers: {}, {}, access_lists: {}, reject: {}, {}",
             self.len(),
-            max_clients,
-            delay.as_secs(),
-            timeout.as_secs(),
-            banner,
+            limits,
+            self.access,
+            reject,
+            tarpit,
         );
-        let banner = Arc::new(banner.into_bytes());
-        for mut listener in self.inner {
-            let banner = banner.clone();
+        let tarpit = Arc::new(tarpit);
+        let limits = Arc::new(limits);
+        let reject = Arc::new(reject);
+        for (name, protocol, mut listener) in self.inner {
+            let tarpit = tarpit.clone();
             let metrics = metrics.clone();
+            let limits = limits.clone();
+            let reject = reject.clone();
+            let access = self.access.clone();
+            let proxy = self.proxy.clone();
+            let knocks = self.knocks.clone();
+            let port = listener.local_addr().map(|addr| addr.port()).unwrap_or(0);
             let server = async move {
                 loop {
                     match listener.accept().await {
                         Ok((sock, peer)) => {
                             let metrics = metrics.clone();
-                            match metrics.connect(max_clients, Instant::now()) {
-                                Ok((connected, token)) => {
-                                    info!("connect, peer: {}, clients: {}", peer, connected);
-                                    tokio::spawn(
-                                        tarpit_connection(
-                                            sock,
-                                            peer,
-                                            delay,
-                                            timeout,
-                                            token,
-                                            metrics.clone(),
-                                            banner.clone()
-                                        )
+                            metrics.accepted(&name);
+                            let knocked = knocks.as_ref().map_or(false, |knocks| knocks.trusts(peer.ip()));
+                            if let Some(knocks) = knocks.as_ref().filter(|_| !knocked) {
+                                knocks.interrupted(peer.ip());
+                            }
+                            if let Some(proxy) = proxy.as_ref().filter(|proxy| knocked || proxy.trusts(peer.ip(), port)) {
+                                tokio::spawn(proxy.clone().splice(sock, peer, metrics));
+                                continue;
+                            }
+                            if knocked {
+                                info!("exclude, peer: {}, listener: {}, protocol: {}, reason: knocked", peer, name, protocol);
+                                continue;
+                            }
+                            if let Some(reason) = access.exclude(peer.ip(), port, &metrics) {
+                                info!("exclude, peer: {}, listener: {}, protocol: {}, reason: {}", peer, name, protocol, reason);
+                                continue;
+                            }
+                            let source = match limits.acquire(peer.ip()) {
+                                Ok(source) => source,
+                                Err(reason) => {
+                                    metrics.limited(reason);
+                                    let mode = reject.reject(sock, peer, protocol, &metrics);
+                                    info!("reject, peer: {}, listener: {}, protocol: {}, reason: {}, mode: {}", peer, name, protocol, reason, mode);
+                                    continue;
+                                },
+                            };
+                            match metrics.connect(&limits, Instant::now(), peer.ip(), protocol) {
+                                Ok((connected, token, evicted)) => {
+                                    info!("connect, peer: {}, listener: {}, protocol: {}, clients: {}", peer, name, protocol, connected);
+                                    let connection = tarpit_connection(
+                                        sock,
+                                        peer,
+                                        protocol,
+                                        token,
+                                        evicted,
+                                        metrics.clone(),
+                                        tarpit.clone(),
+                                    );
+                                    tokio::spawn(async move {
+                                        connection.await;
+                                        drop(source);
+                                    });
+                                },
+                                Err(connected) => {
+                                    metrics.limited("max clients");
+                                    let mode = reject.reject(sock, peer, protocol, &metrics);
+                                    info!(
+                                        "reject, peer: {}, listener: {}, protocol: {}, reason: max clients, clients: {}, mode: {}",
+                                        peer, name, protocol, connected, mode,
                                     );
                                 },
-                                Err(connected) => info!("reject, peer: {}, clients: {}", peer, connected),
                             }
                         }
                         Err(err) => match err.kind() {
@@ -22,41 +22,95 @@
 #![cfg_attr(feature = "nightly", feature(external_doc))]
 #![cfg_attr(feature = "nightly", doc(include = "../README.md"))]
 
+/// Allow and deny lists of networks.
+mod access_list;
+/// Listening sockets inherited from systemd or the parent process.
+mod activation;
+/// Learn how long clients wait.
+mod adaptive;
+/// Helpers for peer addresses.
+mod address;
+/// Learn which banner holds clients longest.
+mod bandit;
+/// Generate the tarpit-messages.
+mod banner;
+/// Split lines into chunks.
+mod chunking;
+/// Occasional surprises between banners.
+mod easter_egg;
 /// Export some statistics.
 #[cfg(feature = "exporters")]
 mod exporters;
+/// A low-interaction SSH server recording login attempts.
+#[cfg(feature = "honeypot")]
+mod honeypot;
+/// Classify clients by their identification string.
+mod identification;
+/// A slow SSH key exchange.
+mod kex;
+/// Trust clients knocking at a sequence of ports.
+mod knock;
+/// Limits of concurrent connections.
+mod limits;
 /// Listen to ssh-connections.
 mod listeners;
 /// Everything to do with keeping track what happend.
 mod logging;
 /// Collect some statistics.
 mod metrics;
+/// What to do with new connections once the tarpit is full.
+mod overload;
+/// Delays between chunks.
+mod pacing;
 /// Drop privileges.
 #[cfg(all(unix, feature = "drop_privs"))]
 mod privilege_dropper;
+/// Protocols of the tarpit.
+mod protocol;
+/// Pass trusted clients through to a real server.
+mod proxy;
+/// Get rid of connections beyond max-clients.
+mod reject;
 /// Parallel execution of tasks.
 mod runtime;
 /// The actual ssh-tarpit.
 mod tarpit;
+/// Expand placeholders of the tarpit-message.
+mod template;
+/// Validate and sanitise the tarpit-message.
+mod validation;
 
+use access_list::{AccessLists, ListFile};
+use activation::ListenFd;
+use adaptive::{Adaptive, AdaptiveKey};
+use bandit::{Bandit, Strategy};
+use banner::{BannerKind, Banners, Rotation};
+use chunking::Chunking;
+use easter_egg::EasterEggs;
+use knock::Knocks;
 use listeners::Listeners;
+use pacing::{Jitter, Pacing};
+use protocol::ListenAddress;
 use log::{error, info, warn};
-#[cfg(not(feature = "exporters"))]
+use limits::Limits;
 use metrics::Metrics;
-#[cfg(not(feature = "exporters"))]
-use std::sync::Arc;
+use overload::Overload;
+use proxy::Proxy;
+use reject::{Reject, RejectMode};
 #[cfg(feature = "exporters")]
 use exporters::Exporter;
 #[cfg(all(unix, feature = "drop_privs"))]
 use privilege_dropper::PrivDropConfig;
 use runtime::Runtime;
+use tarpit::Tarpit;
+use validation::{SshPrefix, Validator};
+#[cfg(feature = "honeypot")]
+use honeypot::Honeypot;
 use std::{
-    io::{
-        BufReader,
-        prelude::*,
-    },
-    fs::File,
-    net::SocketAddr,
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
     time::Duration,
 };
 use structopt::StructOpt;
@@ -67,18 +121,121 @@ use rusty_sandbox::Sandbox;
 #[derive(Debug, StructOpt)]
 #[structopt(name = "tarssh", about = "A SSH tarpit server")]
 struct Config {
-    /// Listen address(es) to bind to of the tarpit.
-    #[structopt(short = "l", long = "listen", default_value = "0.0.0.0:2222")]
-    listen: Vec<SocketAddr>,
+    /// Listen address(es) to bind to of the tarpit, optionally with protocol, e.g. http://0.0.0.0:8081;
+    /// 0.0.0.0:2222 without inherited listeners of --listen-fd or systemd socket activation.
+    #[structopt(short = "l", long = "listen")]
+    listen: Vec<ListenAddress>,
+    /// Inherited listening socket(s) of the tarpit, optionally with protocol, e.g. 3 or http://4.
+    #[structopt(long = "listen-fd")]
+    listen_fd: Vec<ListenFd>,
+    /// Files of networks to limit the tarpit to, one per line, optionally for a port only, e.g. 2222=bad.txt; reloaded on SIGHUP unless sandboxed.
+    #[structopt(long = "allow")]
+    allow: Vec<ListFile>,
+    /// Files of networks to exclude from the tarpit, like --allow; reloaded on SIGHUP unless sandboxed.
+    #[structopt(long = "deny")]
+    deny: Vec<ListFile>,
+    /// Address of a real server to pass trusted clients through to, e.g. 127.0.0.1:2200.
+    #[structopt(long = "proxy-upstream")]
+    proxy_upstream: Option<SocketAddr>,
+    /// Files of networks of trusted clients, like --allow; reloaded on SIGHUP unless sandboxed.
+    #[structopt(long = "proxy-trust")]
+    proxy_trust: Vec<ListFile>,
+    /// Time without any bytes in either direction, before closing a session to --proxy-upstream, e.g. 15m; 0 for none.
+    #[structopt(long = "proxy-idle-timeout", default_value = "15m", parse(try_from_str = pacing::parse_duration))]
+    proxy_idle_timeout: Duration,
+    /// Ports to knock at in order to be passed through to --proxy-upstream or else to bypass the tarpit, not ascending.
+    #[structopt(long = "knock")]
+    knock: Vec<u16>,
+    /// Ports to restart the knock sequence of clients connecting to them, like the tarpit does.
+    #[structopt(long = "knock-decoy")]
+    knock_decoy: Vec<u16>,
+    /// Address to listen for knocks at.
+    #[structopt(long = "knock-address", default_value = "0.0.0.0")]
+    knock_address: IpAddr,
+    /// Time to complete the knock sequence within, e.g. 10s.
+    #[structopt(long = "knock-window", default_value = "10s", parse(try_from_str = pacing::parse_duration))]
+    knock_window: Duration,
+    /// Time to trust clients after a completed knock sequence, e.g. 1h.
+    #[structopt(long = "knock-ttl", default_value = "1h", parse(try_from_str = pacing::parse_duration))]
+    knock_ttl: Duration,
     /// Best-effort connection limit.
     #[structopt(short = "c", long = "max-clients", default_value = "4096")]
     max_clients: u32,
-    /// Seconds between responses.
-    #[structopt(short = "d", long = "delay", default_value = "10")]
-    delay: u64,
-    /// Socket write timeout.
-    #[structopt(short = "t", long = "timeout", default_value = "30")]
-    timeout: u64,
+    /// What to do with new connections beyond max-clients.
+    #[structopt(long = "overload", default_value = "reject-new", possible_values = Overload::VARIANTS)]
+    overload: Overload,
+    /// Concurrent connections per source address; 0 for no limit.
+    #[structopt(long = "max-per-ip", default_value = "0")]
+    max_per_ip: usize,
+    /// Concurrent connections per source network, see --prefix-ipv4 and --prefix-ipv6; 0 for no limit.
+    #[structopt(long = "max-per-prefix", default_value = "0")]
+    max_per_prefix: usize,
+    /// Prefix length of the networks of IPv4-peers for --max-per-prefix.
+    #[structopt(long = "prefix-ipv4", default_value = "24")]
+    prefix_ipv4: u8,
+    /// Prefix length of the networks of IPv6-peers for --max-per-prefix.
+    #[structopt(long = "prefix-ipv6", default_value = "64")]
+    prefix_ipv6: u8,
+    /// How to reject connections beyond max-clients.
+    #[structopt(long = "reject-mode", default_value = "fin", possible_values = RejectMode::VARIANTS)]
+    reject_mode: RejectMode,
+    /// Time to hold rejected connections open with the hold reject-mode, e.g. 5m.
+    #[structopt(long = "reject-hold", default_value = "5m", parse(try_from_str = pacing::parse_duration))]
+    reject_hold: Duration,
+    /// Time between responses, e.g. 10, 250ms or 1m30s.
+    #[structopt(short = "d", long = "delay", default_value = "10", parse(try_from_str = pacing::parse_duration))]
+    delay: Duration,
+    /// Random delay added to every response: none, uniform:<min>-<max>, exponential:<mean> or lognormal:<median>,<sigma>.
+    #[structopt(short = "j", long = "jitter", default_value = "none")]
+    jitter: Jitter,
+    /// Adapt the delay to how long clients wait, grouped by: off or prefix.
+    #[structopt(long = "adaptive", default_value = "off", possible_values = AdaptiveKey::VARIANTS)]
+    adaptive: AdaptiveKey,
+    /// Factor the adaptive delay grows by after every response, above 1 and up to 16.
+    #[structopt(long = "adaptive-growth", default_value = "1.5", parse(try_from_str = adaptive::parse_growth))]
+    adaptive_growth: f64,
+    /// Upper bound of the adaptive delay, e.g. 5m.
+    #[structopt(long = "adaptive-max-delay", default_value = "5m", parse(try_from_str = pacing::parse_duration))]
+    adaptive_max_delay: Duration,
+    /// Size of the chunks sent at once of up to 65535 bytes: fixed:<n>, byte, line or random:<min>-<max>.
+    #[structopt(long = "chunking", default_value = "fixed:16")]
+    chunking: Chunking,
+    /// Socket write timeout, e.g. 30 or 1m.
+    #[structopt(short = "t", long = "timeout", default_value = "30", parse(try_from_str = pacing::parse_duration))]
+    timeout: Duration,
+    /// Probe idle clients with TCP keepalives after this time, e.g. 1m; 0 to disable.
+    #[structopt(long = "keepalive", default_value = "0", parse(try_from_str = pacing::parse_duration))]
+    keepalive: Duration,
+    /// Drop clients not acknowledging sent data within this time (TCP_USER_TIMEOUT, Linux only); 0 to disable.
+    #[structopt(long = "user-timeout", default_value = "0", parse(try_from_str = pacing::parse_duration))]
+    user_timeout: Duration,
+    /// Time to wait for the first bytes of a client on sniff-listeners, e.g. 2s; SMTP needs smtp:// listeners instead.
+    #[structopt(long = "sniff-timeout", default_value = "2s", parse(try_from_str = pacing::parse_duration))]
+    sniff_timeout: Duration,
+    /// Send a real version string and a slow key exchange after this many banners; 0 to never.
+    #[structopt(long = "kex-after", default_value = "0")]
+    kex_after: usize,
+    /// Run a honeypot recording login attempts after this many banners, instead of --kex-after; 0 for right away.
+    #[structopt(long = "honeypot-after", conflicts_with = "kex-after")]
+    #[cfg(feature = "honeypot")]
+    honeypot_after: Option<usize>,
+    /// Seed of the ed25519 host key of the honeypot, generated if missing.
+    #[structopt(long = "honeypot-host-key", default_value = "tarssh_host_ed25519_key")]
+    #[cfg(feature = "honeypot")]
+    honeypot_host_key: PathBuf,
+    /// Delay before rejecting a login attempt to the honeypot, e.g. 3s.
+    #[structopt(long = "honeypot-reject-delay", default_value = "3s", parse(try_from_str = pacing::parse_duration))]
+    #[cfg(feature = "honeypot")]
+    honeypot_reject_delay: Duration,
+    /// Disconnect clients after this time, e.g. 1h; 0 to never.
+    #[structopt(long = "max-session-duration", default_value = "0", parse(try_from_str = pacing::parse_duration))]
+    max_session_duration: Duration,
+    /// Disconnect clients before sending more than this many bytes of the tarpit; 0 to never.
+    #[structopt(long = "max-session-bytes", default_value = "0")]
+    max_session_bytes: usize,
+    /// Line sent to clients disconnected by --max-session-duration or --max-session-bytes, with the placeholders of the tarpit-message.
+    #[structopt(long = "goodbye", default_value = "")]
+    goodbye: String,
     /// Verbose level (repeat for more verbosity).
     #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
     verbose: u8,
@@ -99,21 +256,53 @@ struct Config {
     #[structopt(flatten)]
     #[cfg(all(unix, feature = "drop_privs"))]
     privdrop: PrivDropConfig,
-    /// Filename of the tarpit-message.
-    #[structopt(short = "m", long = "message", default_value = "")]
-    message: String,
+    /// Filename(s) or directories of tarpit-messages, with placeholders {peer_ip}, {peer_port}, {session_id}, {uptime}, {connected_for}, {clients} and {random_word}.
+    #[structopt(short = "m", long = "message")]
+    message: Vec<String>,
+    /// Generator of the tarpit-message.
+    #[structopt(short = "b", long = "banner", default_value = "static", possible_values = BannerKind::VARIANTS)]
+    banner: BannerKind,
+    /// How connections choose between several tarpit-messages.
+    #[structopt(long = "banner-rotation", default_value = "random", possible_values = Rotation::VARIANTS)]
+    banner_rotation: Rotation,
+    /// Switch to another tarpit-message after every banner-loop.
+    #[structopt(long = "banner-switch")]
+    banner_switch: bool,
+    /// Probability of a random tarpit-message with the epsilon-greedy rotation.
+    #[structopt(long = "bandit-epsilon", default_value = "0.1")]
+    bandit_epsilon: f64,
+    /// File to keep what the epsilon-greedy or thompson rotation learned across restarts, opened before dropping privileges.
+    #[structopt(long = "bandit-state", default_value = "")]
+    bandit_state: String,
+    /// Maximum length of a line of the tarpit-message in bytes, at least 8, longer lines are wrapped.
+    #[structopt(long = "max-line-length", default_value = "255")]
+    max_line_length: usize,
+    /// How to handle lines of the tarpit-message starting with "SSH-".
+    #[structopt(long = "ssh-prefix", default_value = "rewrite", possible_values = SshPrefix::VARIANTS)]
+    ssh_prefix: SshPrefix,
+    /// Filename of the easter eggs, one per line as "<id> <weight> <text>".
+    #[structopt(long = "easter-egg-file", default_value = "")]
+    easter_egg_file: String,
+    /// Probability of an easter egg before each banner; 0 to disable.
+    #[structopt(long = "easter-egg-probability", default_value = "0.00390625")]
+    easter_egg_probability: f64,
     /// Listen address(es) to bind to of the exporter.
     #[structopt(short = "e", long = "exporter", default_value = "0.0.0.0:8080")]
     #[cfg(feature = "exporters")]
     exporter: Vec<SocketAddr>,
 }
 
+/// How often to save the learned state of the bandit.
+const BANDIT_SAVE_INTERVAL: Duration = Duration::from_secs(300);
+
 pub(crate) fn errx<M: AsRef<str>>(code: i32, message: M) -> ! {
     error!("{}", message.as_ref());
     std::process::exit(code);
 }
 
 fn main() -> std::io::Result<()> {
+    // Before any thread exists, as this changes the environment.
+    let systemd = activation::systemd();
     let opt = Config::from_args();
 
     logging::init(
@@ -123,11 +312,130 @@ fn main() -> std::io::Result<()> {
         !opt.disable_log_level,
     );
 
+    if opt.max_line_length < validation::MIN_LINE_LENGTH {
+        errx(exitcode::CONFIG, format!("message, error: \"max line length below {} bytes\"", validation::MIN_LINE_LENGTH));
+    }
+    let validator = Validator::new(opt.max_line_length, opt.ssh_prefix);
+    let messages: Vec<(String, String)> = banner::read(&opt.message)
+        .unwrap_or_else(|err| errx(exitcode::NOINPUT, err))
+        .into_iter()
+        .map(|(id, message)| {
+            let message = validator
+                .validate(&message)
+                .unwrap_or_else(|err| errx(exitcode::DATAERR, format!("{}, id: {}", err, id)));
+            (id, message)
+        })
+        .collect();
+    let strategy = match opt.banner_rotation {
+        Rotation::EpsilonGreedy => Some(Strategy::EpsilonGreedy(opt.bandit_epsilon)),
+        Rotation::Thompson      => Some(Strategy::Thompson),
+        _                       => None,
+    };
+    let bandit = strategy.map(|strategy| {
+        let state = Some(PathBuf::from(&opt.bandit_state)).filter(|_| !opt.bandit_state.is_empty());
+        Arc::new(Bandit::new(strategy, state).unwrap_or_else(|err| errx(exitcode::DATAERR, err)))
+    });
+    let banners = Banners::new(
+        opt.banner,
+        &messages,
+        opt.max_line_length,
+        opt.banner_rotation,
+        opt.banner_switch,
+        bandit.clone(),
+    )
+    .unwrap_or_else(|err| errx(exitcode::DATAERR, err));
+
+    let easter_eggs = if opt.easter_egg_file.is_empty() {
+        None
+    } else {
+        Some(
+            std::fs::read_to_string(&opt.easter_egg_file)
+            .unwrap_or_else(|err| errx(exitcode::NOINPUT, format!("easter eggs, file: {}, error: {}", opt.easter_egg_file, err)))
+        )
+    };
+    let easter_eggs = EasterEggs::new(easter_eggs.as_deref(), opt.easter_egg_probability, &validator)
+        .unwrap_or_else(|err| errx(exitcode::DATAERR, err));
+
+    let goodbye = Some(&opt.goodbye).filter(|goodbye| !goodbye.is_empty()).map(|goodbye| {
+        let goodbye = validator
+            .validate(goodbye.as_bytes())
+            .unwrap_or_else(|err| errx(exitcode::DATAERR, format!("{}, id: goodbye", err)));
+        let mut bytes = goodbye.trim_end_matches('\n').replace('\n', "\r\n").into_bytes();
+        bytes.extend_from_slice(b"\r\n");
+        bytes
+    });
+
+    #[cfg(feature = "honeypot")]
+    let honeypot = opt.honeypot_after.map(|after| {
+        let honeypot = Honeypot::new(after, &opt.honeypot_host_key, opt.honeypot_reject_delay)
+            .unwrap_or_else(|err| errx(exitcode::CONFIG, err));
+        info!("honeypot, {}", honeypot);
+        honeypot
+    });
+
+    if opt.prefix_ipv4 > 32 || opt.prefix_ipv6 > 128 {
+        errx(exitcode::CONFIG, format!("limits, error: \"invalid prefix lengths /{} /{}\"", opt.prefix_ipv4, opt.prefix_ipv6));
+    }
+
     let mut runtime = Runtime::new(opt.threads);
 
+    let access_lists = Arc::new(
+        AccessLists::new(opt.allow, opt.deny).unwrap_or_else(|err| errx(exitcode::NOINPUT, err))
+    );
+    if !access_lists.is_empty() {
+        access_lists.spawn_reload(&runtime);
+    }
+
+    #[cfg(all(unix, feature = "sandbox"))]
+    let reloads = !access_lists.is_empty() || (opt.proxy_upstream.is_some() && !opt.proxy_trust.is_empty());
+    let proxy_trust = opt.proxy_trust;
+    let proxy_idle_timeout = Some(opt.proxy_idle_timeout).filter(|idle_timeout| *idle_timeout > Duration::from_secs(0));
+    let proxy = opt.proxy_upstream.map(|upstream| {
+        let proxy = Arc::new(
+            Proxy::new(upstream, proxy_trust, proxy_idle_timeout).unwrap_or_else(|err| errx(exitcode::NOINPUT, err))
+        );
+        info!("proxy, {}", proxy);
+        proxy.spawn_reload(&runtime);
+        proxy
+    });
+
+    if opt.knock.len() > 1 && opt.knock.windows(2).all(|pair| pair[0] < pair[1]) {
+        errx(exitcode::CONFIG, "knock, error: \"ascending sequences are completed by port scans\"");
+    }
+
+    let knocks = if opt.knock.is_empty() {
+        None
+    } else {
+        let knocks = Arc::new(Knocks::new(
+            &mut runtime,
+            opt.knock_address,
+            &opt.knock,
+            &opt.knock_decoy,
+            opt.knock_window,
+            opt.knock_ttl,
+        ));
+        info!("knock, {}", knocks);
+        Some(knocks)
+    };
+
+    let mut inherited = systemd.unwrap_or_else(|err| errx(exitcode::OSERR, err));
+    inherited.extend(opt.listen_fd.iter().map(|listen_fd| (format!("fd{}", listen_fd.fd), *listen_fd)));
+    let mut fds = HashSet::new();
+    if let Some((_, listen_fd)) = inherited.iter().find(|(_, listen_fd)| !fds.insert(listen_fd.fd)) {
+        errx(exitcode::CONFIG, format!("listen, fd: {}, error: \"inherited more than once\"", listen_fd.fd));
+    }
+    let mut listen = opt.listen;
+    if listen.is_empty() && inherited.is_empty() {
+        listen.push("0.0.0.0:2222".parse().unwrap_or_else(|err: String| errx(exitcode::SOFTWARE, err)));
+    }
+
     let listeners = Listeners::new(
         &mut runtime,
-        opt.listen,
+        listen,
+        inherited,
+        access_lists,
+        proxy,
+        knocks.clone(),
     );
 
     #[cfg(feature = "exporters")]
@@ -143,45 +451,75 @@ fn main() -> std::io::Result<()> {
     {
         let sandboxed = Sandbox::new().sandbox_this_process().is_ok();
         info!("sandbox, enabled: {}", sandboxed);
+        if sandboxed && reloads {
+            warn!("sandbox, error: \"list files cannot be reloaded on SIGHUP\"");
+        }
     }
 
+    let metrics = Arc::new(Metrics::new(runtime.start(), opt.chunking.to_string(), bandit.clone()));
     #[cfg(feature = "exporters")]
-    let metrics = exporters.spawn(&runtime);
-    #[cfg(not(feature = "exporters"))]
-    let metrics = std::sync::Arc::new(metrics::Metrics::new(runtime.start()));
+    exporters.spawn(&runtime, &metrics);
+
+    if let Some(knocks) = &knocks {
+        knocks.spawn(&runtime);
+    }
 
     listeners.spawn(
         &runtime,
-        opt.max_clients as usize,
-        Duration::from_secs(opt.delay),
-        Duration::from_secs(opt.timeout),
+        Limits::new(
+            opt.max_clients as usize,
+            opt.overload,
+            Some(opt.max_per_ip).filter(|max_per_ip| *max_per_ip > 0),
+            Some(opt.max_per_prefix).filter(|max_per_prefix| *max_per_prefix > 0),
+            opt.prefix_ipv4,
+            opt.prefix_ipv6,
+        ),
+        Reject {
+            mode:     opt.reject_mode,
+            hold:     opt.reject_hold,
+            max_held: opt.max_clients as usize,
+        },
         metrics.clone(),
-        if opt.message.is_empty() {
-            format!(
-                "{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n{}\r\n",
-                "My name is Yon Yonson",
-                "I live in Wisconsin.",
-                "There, the people I meet",
-                "As I walk down the street",
-                "Say “Hey, what’s your name?”",
-                "And I say:",
-            )
-        } else {
-            BufReader::new(File::open(opt.message)?)
-            .lines()
-            .try_fold(
-                String::new(),
-                |mut result, line| if let Ok(line) = line {
-                    result.push_str(&line);
-                    result.push_str("\r\n");
-                    Ok(result)
+        Tarpit {
+            pacing: Pacing {
+                delay:    opt.delay,
+                jitter:   opt.jitter,
+                adaptive: if opt.adaptive == AdaptiveKey::Off {
+                    None
                 } else {
-                    line
+                    Some(Adaptive::new(opt.adaptive, opt.adaptive_growth, opt.adaptive_max_delay))
                 },
-            )?
+            },
+            time_out:         opt.timeout,
+            chunking:         opt.chunking,
+            send_buffer_size: opt.chunking.send_buffer_size(opt.max_line_length),
+            max_line_length:  opt.max_line_length,
+            keepalive:        Some(opt.keepalive).filter(|keepalive| *keepalive > Duration::from_secs(0)),
+            user_timeout:     Some(opt.user_timeout).filter(|user_timeout| *user_timeout > Duration::from_secs(0)),
+            sniff_timeout:    opt.sniff_timeout,
+            kex_after:        Some(opt.kex_after).filter(|kex_after| *kex_after > 0),
+            max_duration:     Some(opt.max_session_duration).filter(|max_duration| *max_duration > Duration::from_secs(0)),
+            max_bytes:        Some(opt.max_session_bytes).filter(|max_bytes| *max_bytes > 0),
+            goodbye,
+            banners,
+            easter_eggs,
+            #[cfg(feature = "honeypot")]
+            honeypot,
         },
     );
 
+    if let Some(bandit) = bandit.clone() {
+        runtime.spawn(async move {
+            loop {
+                tokio::time::delay_for(BANDIT_SAVE_INTERVAL).await;
+                bandit.save();
+            }
+        });
+    }
+
     runtime.wait(metrics);
+    if let Some(bandit) = bandit {
+        bandit.save();
+    }
     Ok(())
 }
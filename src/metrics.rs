@@ -1,9 +1,11 @@
+#[cfg(feature = "exporters")]
 macro_rules! metric_bucket {
     ($Name:ident ($Bucket:expr): $($Attributes:expr),* $(,)?)
     => {concat!(stringify!($Name), "{{", $($Attributes),*, "}} {", stringify!($Bucket), "}\n",)};
 
 }
 
+#[cfg(feature = "exporters")]
 macro_rules! metric_type {
     (counter)   => {"counter"};
     (gauge)     => {"gauge"};
@@ -12,6 +14,7 @@ macro_rules! metric_type {
     (untyped)   => {"untyped"};
 }
 
+#[cfg(feature = "exporters")]
 macro_rules! metric_header {
   (
       $Name:ident:
@@ -25,6 +28,7 @@ macro_rules! metric_header {
   };
 }
 
+#[cfg(feature = "exporters")]
 macro_rules! metric {
     (
         $Name:ident:
@@ -34,21 +38,90 @@ macro_rules! metric {
         concat!(
             metric_header!($Name: $Type, $Description),
             stringify!($Name), " {", stringify!($Name), "}\n\n",
-        );
+        )
     };
 }
 
+use super::{
+    bandit::Bandit,
+    limits::Limits,
+    overload::Overload,
+    protocol::Protocol,
+    reject::RejectMode,
+};
 use std::{
     borrow::Cow,
-    sync::{atomic::{AtomicUsize, Ordering}, Mutex},
-    time::Instant,
+    collections::BTreeMap,
+    net::IpAddr,
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex},
+    time::{Duration, Instant},
 };
+#[cfg(feature = "exporters")]
+use std::fmt::{Display, Write};
+use tokio::sync::oneshot;
+
+/// Distinct HASSH-fingerprints to count, before counting them as `other`.
+const MAX_FINGERPRINTS: usize = 1024;
+
+/// Escape a label value as per the text exposition format, i.e. backslashes, double quotes and newlines.
+#[cfg(feature = "exporters")]
+pub(crate) fn escape<Label: Display>(value: Label) -> String {
+    let value = value.to_string();
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Append a metric with a single label and one sample per label-value.
+#[cfg(feature = "exporters")]
+fn labelled<Label, Value, Values>(
+    export:       &mut String,
+    name:         &str,
+    kind:         &str,
+    description:  &str,
+    label:        &str,
+    values:       Values,
+)
+where
+    Label:  Display,
+    Value:  Display,
+    Values: IntoIterator<Item = (Label, Value)>,
+{
+    let _ = write!(export, "\n# HELP {} {}\n# TYPE {} {}\n", name, description, name, kind);
+    for (value, count) in values {
+        let _ = writeln!(export, "{}{{{}=\"{}\"}} {}", name, label, escape(value), count);
+    }
+}
 
 pub(crate) struct Client {
+    /// Tells apart the clients taking turns in the same slot.
+    generation:       usize,
     start:            Instant,
+    peer:             IpAddr,
+    evict:            Option<oneshot::Sender<()>>,
     sent_chunks:      u64,
+    sent_bytes:       u64,
     sent_eastereggs:  u64,
     sent_banners:     u64,
+    class:            Option<&'static str>,
+    #[cfg(feature = "exporters")]
+    protocol:         Protocol,
+    banner:           Option<String>,
+}
+
+/// Statistics of a single banner.
+#[derive(Default)]
+struct BannerMetrics {
+    sent:         usize,
+    disconnects:  usize,
+    hold_seconds: f64,
 }
 
 pub(crate) struct ClientMetrics {
@@ -57,6 +130,7 @@ pub(crate) struct ClientMetrics {
     connection_time_till:     [usize; 32],
     connection_time:          u64,
     sent_chunks_sum:          u64,
+    sent_bytes_sum:           u64,
     sent_eastereggs_sum:      u64,
     sent_banners_sum:         u64,
 }
@@ -69,6 +143,7 @@ impl ClientMetrics {
             connection_time_till:     [0usize; 32],
             connection_time:          0,
             sent_chunks_sum:          0,
+            sent_bytes_sum:           0,
             sent_eastereggs_sum:      0,
             sent_banners_sum:         0,
         }
@@ -81,41 +156,120 @@ pub(crate) struct Metrics {
     former_metrics:     Mutex<ClientMetrics>,
     connections_count:  AtomicUsize,
     connections_total:  AtomicUsize,
+    #[cfg(feature = "exporters")]
+    chunking:           String,
+    chunk_size_till:    [AtomicUsize; 16],
+    disconnect_reasons: Mutex<BTreeMap<&'static str, usize>>,
+    client_classes:     Mutex<BTreeMap<&'static str, usize>>,
+    protocols_total:    Mutex<BTreeMap<Protocol, usize>>,
+    sniffed:            Mutex<BTreeMap<&'static str, usize>>,
+    stage_seconds:      Mutex<BTreeMap<&'static str, f64>>,
+    fingerprints:       Mutex<BTreeMap<String, usize>>,
+    #[cfg(any(feature = "exporters", feature = "honeypot"))]
+    login_attempts:     Mutex<BTreeMap<&'static str, usize>>,
+    eastereggs:         Mutex<BTreeMap<String, usize>>,
+    banners:            Mutex<BTreeMap<String, BannerMetrics>>,
+    evictions:          Mutex<BTreeMap<Overload, usize>>,
+    rejects:            Mutex<BTreeMap<RejectMode, usize>>,
+    limited:            Mutex<BTreeMap<&'static str, usize>>,
+    access_lists:       Mutex<BTreeMap<(&'static str, bool), usize>>,
+    proxied:            AtomicUsize,
+    proxied_total:      AtomicUsize,
+    proxy_failures:     AtomicUsize,
+    proxy_bytes:        Mutex<BTreeMap<&'static str, u64>>,
+    listeners:          Mutex<BTreeMap<String, usize>>,
+    held:               AtomicUsize,
+    bandit:             Option<Arc<Bandit>>,
 }
 
 impl Metrics {
     pub(crate) fn new(
         startup: Instant,
+        chunking: String,
+        bandit: Option<Arc<Bandit>>,
     ) -> Self {
+        #[cfg(not(feature = "exporters"))]
+        let _ = chunking;
         Self {
             startup,
             clients:            Mutex::new(Vec::new()),
             former_metrics:     Mutex::new(ClientMetrics::new()),
             connections_count:  AtomicUsize::new(0),
             connections_total:  AtomicUsize::new(0),
+            #[cfg(feature = "exporters")]
+            chunking,
+            chunk_size_till:    Default::default(),
+            disconnect_reasons: Mutex::new(BTreeMap::new()),
+            client_classes:     Mutex::new(BTreeMap::new()),
+            protocols_total:    Mutex::new(BTreeMap::new()),
+            sniffed:            Mutex::new(BTreeMap::new()),
+            stage_seconds:      Mutex::new(BTreeMap::new()),
+            fingerprints:       Mutex::new(BTreeMap::new()),
+            #[cfg(any(feature = "exporters", feature = "honeypot"))]
+            login_attempts:     Mutex::new(BTreeMap::new()),
+            eastereggs:         Mutex::new(BTreeMap::new()),
+            banners:            Mutex::new(BTreeMap::new()),
+            evictions:          Mutex::new(BTreeMap::new()),
+            rejects:            Mutex::new(BTreeMap::new()),
+            limited:            Mutex::new(BTreeMap::new()),
+            access_lists:       Mutex::new(BTreeMap::new()),
+            proxied:            AtomicUsize::new(0),
+            proxied_total:      AtomicUsize::new(0),
+            proxy_failures:     AtomicUsize::new(0),
+            proxy_bytes:        Mutex::new(BTreeMap::new()),
+            listeners:          Mutex::new(BTreeMap::new()),
+            held:               AtomicUsize::new(0),
+            bandit,
         }
     }
 
+    pub(crate) fn uptime(&self) -> Duration {
+        self.startup.elapsed()
+    }
+
     pub(crate) fn connections(&self) -> usize {
         self.connections_count.load(Ordering::Relaxed)
     }
 
+    /// Register a new client, evicting a session as per the overload policy of `limits` once `max_clients` is reached.
+    /// The receiver completes, once the session of the client is evicted.
     pub(crate) fn connect(
         &self,
-        max_clients: usize,
+        limits: &Limits,
         start: Instant,
-    ) -> Result<(usize, Token), usize> {
-        self.connections_total.fetch_add(1, Ordering::Relaxed);
-        let connected = self.connections_count.fetch_add(1, Ordering::Relaxed) + 1;
-        if connected > max_clients {
+        peer: IpAddr,
+        protocol: Protocol,
+    ) -> Result<(usize, Token, oneshot::Receiver<()>), usize> {
+        let generation = self.connections_total.fetch_add(1, Ordering::Relaxed);
+        {
+            let mut protocols = match self.protocols_total.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            *protocols.entry(protocol).or_insert(0) += 1;
+        }
+        let mut connected = self.connections_count.fetch_add(1, Ordering::Relaxed) + 1;
+        if connected > limits.max_clients && !self.evict(limits) {
             self.connections_count.fetch_sub(1, Ordering::Relaxed);
             Err(connected)
         } else {
+            if connected > limits.max_clients {
+                connected -= 1;
+            }
+            let (evict, evicted) = oneshot::channel();
             let client = Client {
+                generation,
                 start,
+                peer,
+                evict:            Some(evict),
                 sent_chunks:      0,
+                sent_bytes:       0,
                 sent_eastereggs:  0,
                 sent_banners:     0,
+                class:            None,
+                #[cfg(feature = "exporters")]
+                protocol,
+                banner:           None,
             };
             let mut guard = match self.clients.lock() {
                 Ok(guard) => guard,
@@ -124,6 +278,7 @@ impl Metrics {
             Ok((
                 connected,
                 Token {
+                    generation,
                     uid: if let Some(index) = guard
                         .iter()
                         .enumerate()
@@ -142,35 +297,200 @@ impl Metrics {
                         guard.len() - 1
                     }
                 },
+                evicted,
             ))
         }
     }
 
+    /// Evict a session as per the overload policy of `limits` to make room for a new client,
+    /// returns whether one was evicted.
+    fn evict(
+        &self,
+        limits: &Limits,
+    ) -> bool {
+        let mut guard = match self.clients.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let victim = limits.overload.victim(
+            guard
+                .iter()
+                .enumerate()
+                .filter_map(|(index, client)| client.as_ref().map(|client| (index, client.start, client.peer))),
+            limits.ipv4_bits,
+            limits.ipv6_bits,
+        );
+        let mut client = match victim.and_then(|index| guard[index].take()) {
+            Some(client) => client,
+            None => return false,
+        };
+        self.connections_count.fetch_sub(1, Ordering::Relaxed);
+        self.retire(&client, "evicted");
+        if let Some(evict) = client.evict.take() {
+            let _ = evict.send(());
+        }
+        let mut evictions = match self.evictions.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *evictions.entry(limits.overload).or_insert(0) += 1;
+        true
+    }
+
+    /// Count a connection rejected with `mode`.
+    pub(crate) fn rejected(
+        &self,
+        mode: RejectMode,
+    ) {
+        let mut rejects = match self.rejects.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *rejects.entry(mode).or_insert(0) += 1;
+    }
+
+    /// Count a connection rejected for exceeding the limit `reason`, e.g. `max per ip`.
+    pub(crate) fn limited(
+        &self,
+        reason: &'static str,
+    ) {
+        let mut limited = match self.limited.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *limited.entry(reason).or_insert(0) += 1;
+    }
+
+    /// Count a lookup of a client in the allow or deny `list`.
+    pub(crate) fn access_list(
+        &self,
+        list: &'static str,
+        matched: bool,
+    ) {
+        let mut access_lists = match self.access_lists.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *access_lists.entry((list, matched)).or_insert(0) += 1;
+    }
+
+    /// Count a connection accepted by the listener named `listener`.
+    pub(crate) fn accepted(
+        &self,
+        listener: &str,
+    ) {
+        let mut listeners = match self.listeners.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match listeners.get_mut(listener) {
+            Some(count) => *count += 1,
+            None => {
+                listeners.insert(listener.to_owned(), 1);
+            },
+        }
+    }
+
+    /// Number of current proxy sessions.
+    pub(crate) fn proxied(&self) -> usize {
+        self.proxied.load(Ordering::Relaxed)
+    }
+
+    /// Count a new proxy session.
+    pub(crate) fn proxy_started(&self) {
+        self.proxied.fetch_add(1, Ordering::Relaxed);
+        self.proxied_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a finished proxy session, which forwarded `sent` bytes to and `received` bytes from the upstream.
+    pub(crate) fn proxy_finished(
+        &self,
+        sent: u64,
+        received: u64,
+        failed: bool,
+    ) {
+        self.proxied.fetch_sub(1, Ordering::Relaxed);
+        if failed {
+            self.proxy_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut bytes = match self.proxy_bytes.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *bytes.entry("upstream").or_insert(0) += sent;
+        *bytes.entry("downstream").or_insert(0) += received;
+    }
+
+    /// Count a rejected connection held open, unless `max_held` are held already, returns whether to hold it.
+    pub(crate) fn hold(
+        &self,
+        max_held: usize,
+    ) -> bool {
+        self.held
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |held| Some(held + 1).filter(|held| *held <= max_held))
+            .is_ok()
+    }
+
+    /// A held connection was closed.
+    pub(crate) fn released(&self) {
+        self.held.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Account for the former client, returns its connection time.
+    fn retire(
+        &self,
+        client: &Client,
+        reason: &'static str,
+    ) -> u64 {
+        let mut metrics_guard = match self.former_metrics.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let connection_time = client.start.elapsed().as_secs();
+        metrics_guard.maximum_connection_time = metrics_guard.maximum_connection_time.max(connection_time);
+        metrics_guard.minimum_connection_time = metrics_guard.minimum_connection_time.min(connection_time);
+        let bucket = 63usize.saturating_sub(connection_time.leading_zeros() as usize).min(metrics_guard.connection_time_till.len() - 1);
+        metrics_guard.connection_time_till[bucket] += 1;
+        metrics_guard.connection_time     += connection_time;
+        metrics_guard.sent_chunks_sum     += client.sent_chunks;
+        metrics_guard.sent_bytes_sum      += client.sent_bytes;
+        metrics_guard.sent_eastereggs_sum += client.sent_eastereggs;
+        metrics_guard.sent_banners_sum    += client.sent_banners;
+        if let Some(banner) = &client.banner {
+            let mut banners = match self.banners.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            let hold_seconds = client.start.elapsed().as_secs_f64();
+            let metrics = banners.entry(banner.clone()).or_default();
+            metrics.disconnects  += 1;
+            metrics.hold_seconds += hold_seconds;
+            if let Some(bandit) = &self.bandit {
+                bandit.observe(client.class.unwrap_or("unidentified"), banner, hold_seconds);
+            }
+        }
+        let mut reasons = match self.disconnect_reasons.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *reasons.entry(reason).or_insert(0) += 1;
+        connection_time
+    }
+
     pub(crate) fn disconnect(
         &self,
         token: Token,
+        reason: &'static str,
     ) -> Result<(usize, u64), Cow<'static, str>> {
       let mut guard = match self.clients.lock() {
           Ok(guard) => guard,
           Err(poisoned) => poisoned.into_inner(),
       };
-      let mut metrics_guard = match self.former_metrics.lock() {
-          Ok(guard) => guard,
-          Err(poisoned) => poisoned.into_inner(),
-      };
       if guard.len() > token.uid {
-          if let Some(ref client) = guard[token.uid] {
+          let current = guard[token.uid].as_ref().map_or(false, |client| client.generation == token.generation);
+          if let Some(client) = if current { guard[token.uid].take() } else { None } {
               let connected = self.connections_count.fetch_sub(1, Ordering::Relaxed);
-              let connection_time = client.start.elapsed().as_secs();
-              metrics_guard.maximum_connection_time = metrics_guard.maximum_connection_time.max(connection_time);
-              metrics_guard.minimum_connection_time = metrics_guard.minimum_connection_time.min(connection_time);
-              let bucket = 63-connection_time.leading_zeros() as usize;
-              metrics_guard.connection_time_till[bucket] += 1;
-              metrics_guard.connection_time     += connection_time;
-              metrics_guard.sent_chunks_sum     += client.sent_chunks;
-              metrics_guard.sent_eastereggs_sum += client.sent_eastereggs;
-              metrics_guard.sent_banners_sum    += client.sent_banners;
-              guard[token.uid] = None;
+              let connection_time = self.retire(&client, reason);
               Ok((connected-1, connection_time))
           } else {
               Err(Cow::Borrowed("Already Disconnected"))
@@ -180,11 +500,16 @@ impl Metrics {
       }
     }
 
+    #[cfg(feature = "exporters")]
     pub(crate) fn export(&self) -> String {
         let client_guard = match self.clients.lock() {
             Ok(guard) => guard,
             Err(poisoned) => poisoned.into_inner(),
         };
+        let mut protocols_count = BTreeMap::new();
+        for client in client_guard.iter().flatten() {
+            *protocols_count.entry(client.protocol).or_insert(0) += 1;
+        }
         let client_metrics = client_guard
             .iter()
             .fold(
@@ -194,10 +519,11 @@ impl Metrics {
                         let connection_time = client.start.elapsed().as_secs();
                         metrics.maximum_connection_time = metrics.maximum_connection_time.max(connection_time);
                         metrics.minimum_connection_time = metrics.minimum_connection_time.min(connection_time);
-                        let bucket = 63-connection_time.leading_zeros() as usize;
+                        let bucket = 63usize.saturating_sub(connection_time.leading_zeros() as usize).min(metrics.connection_time_till.len() - 1);
                         metrics.connection_time_till[bucket] += 1;
                         metrics.connection_time     += connection_time;
                         metrics.sent_chunks_sum     += client.sent_chunks;
+                        metrics.sent_bytes_sum      += client.sent_bytes;
                         metrics.sent_eastereggs_sum += client.sent_eastereggs;
                         metrics.sent_banners_sum    += client.sent_banners;
                     }
@@ -208,7 +534,7 @@ impl Metrics {
             Ok(guard) => guard,
             Err(poisoned) => poisoned.into_inner(),
         };
-        format!(
+        let mut export = format!(
             concat!(
                 metric!       (uptime_seconds:                          gauge,      "Number of seconds since startup."                              ),
                 metric!       (connections_count:                       counter,    "Number of current connections."                                ),
@@ -216,6 +542,7 @@ impl Metrics {
                 metric!       (client_maximum_connection_time_seconds:  counter,    "Length in seconds of longest connection by current clients."   ),
                 metric!       (client_minimum_connection_time_seconds:  counter,    "Length in seconds of shortest connection by current clients."  ),
                 metric!       (client_sent_chunks_sum:                  counter,    "Sum of sent chunks by current clients."                        ),
+                metric!       (client_sent_bytes_sum:                   counter,    "Sum of sent bytes by current clients."                         ),
                 metric!       (client_sent_eastereggs_sum:              counter,    "Sum of sent sent_eastereggs by current clients."               ),
                 metric!       (client_sent_banners_sum:                 counter,    "Sum of sent banners by current clients."                       ),
                 metric!       (client_connection_time_seconds_sum:      counter,    "Sum of connection time of current clients."                    ),
@@ -256,6 +583,7 @@ impl Metrics {
                 metric!       (former_maximum_connection_time_seconds:  counter,    "Length in seconds of longest connection by former clients."  ),
                 metric!       (former_minimum_connection_time_seconds:  counter,    "Length in seconds of shortest connection by former clients." ),
                 metric!       (former_sent_chunks_sum:                  counter,    "Sum of sent chunks by former clients."                       ),
+                metric!       (former_sent_bytes_sum:                   counter,    "Sum of sent bytes by former clients."                        ),
                 metric!       (former_sent_eastereggs_sum:              counter,    "Sum of sent sent_eastereggs by former clients."              ),
                 metric!       (former_sent_banners_sum:                 counter,    "Sum of sent banners by former clients."                      ),
                 metric!       (former_connection_time_seconds_sum:      counter,    "Sum of connection time of former clients."                    ),
@@ -296,6 +624,7 @@ impl Metrics {
                 metric!       (total_maximum_connection_time_seconds:  counter,    "Length in seconds of longest connection overall."   ),
                 metric!       (total_minimum_connection_time_seconds:  counter,    "Length in seconds of shortest connection overall."  ),
                 metric!       (total_sent_chunks_sum:                  counter,    "Sum of sent chunks overall."                        ),
+                metric!       (total_sent_bytes_sum:                   counter,    "Sum of sent bytes overall."                         ),
                 metric!       (total_sent_eastereggs_sum:              counter,    "Sum of sent sent_eastereggs overall."               ),
                 metric!       (total_sent_banners_sum:                 counter,    "Sum of sent banners overall."                       ),
                 metric!       (total_connection_time_seconds_sum:      counter,    "Sum of connection time overall."                    ),
@@ -332,6 +661,24 @@ impl Metrics {
                 metric_bucket!(total_connection_time_seconds_bucket (total_connection_time_bucket1d):  "le=536870911s",),
                 metric_bucket!(total_connection_time_seconds_bucket (total_connection_time_bucket1e):  "le=1073741823s",),
                 metric_bucket!(total_connection_time_seconds_bucket (total_connection_time_bucket1f):  "le=2147483647s",),
+                "\n",
+                metric_header!(sent_chunk_size_bytes_bucket:  histogram,  "A histogram of the size of sent chunks."),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket00):  "le=1,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket01):  "le=3,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket02):  "le=7,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket03):  "le=15,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket04):  "le=31,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket05):  "le=63,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket06):  "le=127,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket07):  "le=255,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket08):  "le=511,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket09):  "le=1023,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket0a):  "le=2047,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket0b):  "le=4095,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket0c):  "le=8191,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket0d):  "le=16383,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket0e):  "le=32767,chunking=\"{chunking}\"",),
+                metric_bucket!(sent_chunk_size_bytes_bucket (chunk_size_bucket0f):  "le=65535,chunking=\"{chunking}\"",),
             ),
             uptime_seconds                          = self.startup.elapsed().as_secs(),
             connections_count                       = self.connections_count.load(Ordering::Relaxed),
@@ -339,6 +686,7 @@ impl Metrics {
             client_maximum_connection_time_seconds  = client_metrics.maximum_connection_time,
             client_minimum_connection_time_seconds  = client_metrics.minimum_connection_time,
             client_sent_chunks_sum                  = client_metrics.sent_chunks_sum,
+            client_sent_bytes_sum                   = client_metrics.sent_bytes_sum,
             client_sent_eastereggs_sum              = client_metrics.sent_eastereggs_sum,
             client_sent_banners_sum                 = client_metrics.sent_banners_sum,
             client_connection_time_seconds_sum      = client_metrics.connection_time,
@@ -377,6 +725,7 @@ impl Metrics {
             former_maximum_connection_time_seconds  = former_metrics.maximum_connection_time,
             former_minimum_connection_time_seconds  = former_metrics.minimum_connection_time,
             former_sent_chunks_sum                  = former_metrics.sent_chunks_sum,
+            former_sent_bytes_sum                   = former_metrics.sent_bytes_sum,
             former_sent_eastereggs_sum              = former_metrics.sent_eastereggs_sum,
             former_sent_banners_sum                 = former_metrics.sent_banners_sum,
             former_connection_time_seconds_sum      = former_metrics.connection_time,
@@ -415,6 +764,7 @@ impl Metrics {
             total_maximum_connection_time_seconds   = client_metrics.maximum_connection_time.max(former_metrics.maximum_connection_time),
             total_minimum_connection_time_seconds   = client_metrics.minimum_connection_time.min(former_metrics.maximum_connection_time),
             total_sent_chunks_sum                   = client_metrics.sent_chunks_sum      + former_metrics.sent_chunks_sum,
+            total_sent_bytes_sum                    = client_metrics.sent_bytes_sum       + former_metrics.sent_bytes_sum,
             total_sent_eastereggs_sum               = client_metrics.sent_eastereggs_sum  + former_metrics.sent_eastereggs_sum,
             total_sent_banners_sum                  = client_metrics.sent_banners_sum     + former_metrics.sent_banners_sum,
             total_connection_time_seconds_sum       = client_metrics.connection_time      + former_metrics.connection_time,
@@ -450,7 +800,269 @@ impl Metrics {
             total_connection_time_bucket1d          = client_metrics.connection_time_till[0x1d] + former_metrics.connection_time_till[0x1d],
             total_connection_time_bucket1e          = client_metrics.connection_time_till[0x1e] + former_metrics.connection_time_till[0x1e],
             total_connection_time_bucket1f          = client_metrics.connection_time_till[0x1f] + former_metrics.connection_time_till[0x1f],
-        )
+            chunking                                = self.chunking,
+            chunk_size_bucket00                     = self.chunk_size_till[0x00].load(Ordering::Relaxed),
+            chunk_size_bucket01                     = self.chunk_size_till[0x01].load(Ordering::Relaxed),
+            chunk_size_bucket02                     = self.chunk_size_till[0x02].load(Ordering::Relaxed),
+            chunk_size_bucket03                     = self.chunk_size_till[0x03].load(Ordering::Relaxed),
+            chunk_size_bucket04                     = self.chunk_size_till[0x04].load(Ordering::Relaxed),
+            chunk_size_bucket05                     = self.chunk_size_till[0x05].load(Ordering::Relaxed),
+            chunk_size_bucket06                     = self.chunk_size_till[0x06].load(Ordering::Relaxed),
+            chunk_size_bucket07                     = self.chunk_size_till[0x07].load(Ordering::Relaxed),
+            chunk_size_bucket08                     = self.chunk_size_till[0x08].load(Ordering::Relaxed),
+            chunk_size_bucket09                     = self.chunk_size_till[0x09].load(Ordering::Relaxed),
+            chunk_size_bucket0a                     = self.chunk_size_till[0x0a].load(Ordering::Relaxed),
+            chunk_size_bucket0b                     = self.chunk_size_till[0x0b].load(Ordering::Relaxed),
+            chunk_size_bucket0c                     = self.chunk_size_till[0x0c].load(Ordering::Relaxed),
+            chunk_size_bucket0d                     = self.chunk_size_till[0x0d].load(Ordering::Relaxed),
+            chunk_size_bucket0e                     = self.chunk_size_till[0x0e].load(Ordering::Relaxed),
+            chunk_size_bucket0f                     = self.chunk_size_till[0x0f].load(Ordering::Relaxed),
+        );
+
+        let reasons = match self.disconnect_reasons.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "disconnects_total",
+            metric_type!(counter),
+            "Number of disconnects by reason.",
+            "reason",
+            reasons.iter().map(|(reason, count)| (reason, *count)),
+        );
+
+        let evictions = match self.evictions.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "evictions_total",
+            metric_type!(counter),
+            "Number of sessions evicted for new clients by overload policy.",
+            "policy",
+            evictions.iter().map(|(policy, count)| (policy, *count)),
+        );
+
+        let rejects = match self.rejects.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "rejects_total",
+            metric_type!(counter),
+            "Number of connections rejected beyond max-clients by reject mode.",
+            "mode",
+            rejects.iter().map(|(mode, count)| (mode, *count)),
+        );
+        let limited = match self.limited.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "rejected_connections_total",
+            metric_type!(counter),
+            "Number of rejected connections by the exceeded limit.",
+            "reason",
+            limited.iter().map(|(reason, count)| (reason, *count)),
+        );
+        let access_lists = match self.access_lists.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for (name, description, matched) in &[
+            ("access_list_matched_total", "Number of clients on the allow or deny lists.", true),
+            ("access_list_unmatched_total", "Number of clients not on the allow or deny lists.", false),
+        ] {
+            labelled(
+                &mut export,
+                name,
+                metric_type!(counter),
+                description,
+                "list",
+                access_lists
+                    .iter()
+                    .filter(|((_, entry_matched), _)| entry_matched == matched)
+                    .map(|((list, _), count)| (list, *count)),
+            );
+        }
+        let _ = write!(
+            export,
+            "\n{}proxy_sessions {}\n\n{}proxy_sessions_total {}\n\n{}proxy_failures_total {}\n",
+            metric_header!(proxy_sessions: gauge, "Number of current sessions passed through to the upstream."),
+            self.proxied.load(Ordering::Relaxed),
+            metric_header!(proxy_sessions_total: counter, "Number of sessions passed through to the upstream."),
+            self.proxied_total.load(Ordering::Relaxed),
+            metric_header!(proxy_failures_total: counter, "Number of sessions passed through to the upstream ending with an error."),
+            self.proxy_failures.load(Ordering::Relaxed),
+        );
+        let listeners = match self.listeners.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "listener_connections_total",
+            metric_type!(counter),
+            "Total number of accepted connections by listener.",
+            "listener",
+            listeners.iter().map(|(listener, count)| (listener, *count)),
+        );
+        let proxy_bytes = match self.proxy_bytes.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "proxy_bytes_total",
+            metric_type!(counter),
+            "Bytes passed through by direction.",
+            "direction",
+            proxy_bytes.iter().map(|(direction, bytes)| (direction, *bytes)),
+        );
+        let _ = write!(
+            export,
+            "\n{}held_connections {}\n",
+            metric_header!(held_connections: gauge, "Number of rejected connections held open."),
+            self.held.load(Ordering::Relaxed),
+        );
+
+        let classes = match self.client_classes.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "client_classes_total",
+            metric_type!(counter),
+            "Number of clients by the class of their identification string.",
+            "class",
+            classes.iter().map(|(class, count)| (class, *count)),
+        );
+
+        let protocols = match self.protocols_total.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "protocol_connections_total",
+            metric_type!(counter),
+            "Total number of connections by protocol.",
+            "protocol",
+            protocols.iter().map(|(protocol, count)| (protocol, *count)),
+        );
+        labelled(
+            &mut export,
+            "protocol_connections_count",
+            metric_type!(gauge),
+            "Number of current connections by protocol.",
+            "protocol",
+            protocols_count,
+        );
+
+        let sniffed = match self.sniffed.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "sniffed_protocols_total",
+            metric_type!(counter),
+            "Number of sniffed connections by detected protocol.",
+            "detected",
+            sniffed.iter().map(|(detected, count)| (detected, *count)),
+        );
+
+        let stage_seconds = match self.stage_seconds.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "stage_seconds_total",
+            metric_type!(counter),
+            "Time spent by former clients in each stage of the tarpit.",
+            "stage",
+            stage_seconds.iter().map(|(stage, seconds)| (stage, *seconds)),
+        );
+
+        let fingerprints = match self.fingerprints.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "client_hassh_total",
+            metric_type!(counter),
+            "Number of clients by the HASSH-fingerprint of their KEXINIT.",
+            "hassh",
+            fingerprints.iter().map(|(hassh, count)| (hassh, *count)),
+        );
+
+        let login_attempts = match self.login_attempts.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "login_attempts_total",
+            metric_type!(counter),
+            "Number of login attempts rejected by the honeypot by method.",
+            "method",
+            login_attempts.iter().map(|(method, count)| (method, *count)),
+        );
+
+        let eastereggs = match self.eastereggs.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "sent_eastereggs_total",
+            metric_type!(counter),
+            "Number of sent eastereggs by egg.",
+            "egg",
+            eastereggs.iter().map(|(egg, count)| (egg, *count)),
+        );
+
+        let banners = match self.banners.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        labelled(
+            &mut export,
+            "banner_sent_total",
+            metric_type!(counter),
+            "Number of completed banner-loops by banner.",
+            "banner",
+            banners.iter().map(|(banner, metrics)| (banner, metrics.sent)),
+        );
+        labelled(
+            &mut export,
+            "banner_disconnects_total",
+            metric_type!(counter),
+            "Number of former clients by the banner shown last.",
+            "banner",
+            banners.iter().map(|(banner, metrics)| (banner, metrics.disconnects)),
+        );
+        labelled(
+            &mut export,
+            "banner_hold_seconds_sum",
+            metric_type!(counter),
+            "Connection time of former clients by the banner shown last.",
+            "banner",
+            banners.iter().map(|(banner, metrics)| (banner, metrics.hold_seconds)),
+        );
+
+        if let Some(bandit) = &self.bandit {
+            bandit.export(&mut export);
+        }
+
+        export
     }
 
     fn in_client<Func>(
@@ -464,11 +1076,12 @@ impl Metrics {
             Err(poisoned) => poisoned.into_inner(),
         };
         if guard.len() > token.uid {
-            if let Some(ref mut entry) = guard[token.uid] {
-                action(entry);
-                Ok(())
-            } else {
-                Err("Already Disconnected")
+            match guard[token.uid] {
+                Some(ref mut entry) if entry.generation == token.generation => {
+                    action(entry);
+                    Ok(())
+                },
+                _ => Err("Already Disconnected"),
             }
         } else {
             Err("Invalid Token")
@@ -478,25 +1091,163 @@ impl Metrics {
     pub(crate) fn sent_chunk(
         &self,
         token: &Token,
+        size: usize,
+    ) -> Result<(), &'static str> {
+        let bucket = (63 - (size as u64).leading_zeros() as usize).min(self.chunk_size_till.len() - 1);
+        self.chunk_size_till[bucket].fetch_add(1, Ordering::Relaxed);
+        self.in_client(token, |client: &mut Client| {
+            client.sent_chunks += 1;
+            client.sent_bytes += size as u64;
+        })
+    }
+
+    pub(crate) fn identified(
+        &self,
+        token: &Token,
+        class: &'static str,
     ) -> Result<(), &'static str> {
-        self.in_client(token, |client: &mut Client| client.sent_chunks += 1)
+        self.in_client(token, |client: &mut Client| client.class = Some(class))?;
+        let mut classes = match self.client_classes.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *classes.entry(class).or_insert(0) += 1;
+        Ok(())
+    }
+
+    pub(crate) fn sniffed(
+        &self,
+        detected: &'static str,
+    ) {
+        let mut sniffed = match self.sniffed.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *sniffed.entry(detected).or_insert(0) += 1;
+    }
+
+    pub(crate) fn spent(
+        &self,
+        stage: &'static str,
+        time: Duration,
+    ) {
+        let mut stage_seconds = match self.stage_seconds.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *stage_seconds.entry(stage).or_insert(0.0) += time.as_secs_f64();
+    }
+
+    pub(crate) fn fingerprinted(
+        &self,
+        hassh: String,
+    ) {
+        let mut fingerprints = match self.fingerprints.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let hassh = if fingerprints.len() < MAX_FINGERPRINTS || fingerprints.contains_key(&hassh) {
+            hassh
+        } else {
+            "other".to_owned()
+        };
+        *fingerprints.entry(hassh).or_insert(0) += 1;
+    }
+
+    #[cfg(feature = "honeypot")]
+    pub(crate) fn login_attempt(
+        &self,
+        method: &'static str,
+    ) {
+        let mut login_attempts = match self.login_attempts.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *login_attempts.entry(method).or_insert(0) += 1;
     }
 
     pub(crate) fn sent_easteregg(
         &self,
         token: &Token,
+        id: &str,
     ) -> Result<(), &'static str> {
+        {
+            let mut eastereggs = match self.eastereggs.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            match eastereggs.get_mut(id) {
+                Some(count) => *count += 1,
+                None => {
+                    eastereggs.insert(id.to_owned(), 1);
+                },
+            }
+        }
         self.in_client(token, |client: &mut Client| client.sent_eastereggs += 1)
     }
 
+    /// The client is shown the banner `id` from now on.
+    pub(crate) fn showing(
+        &self,
+        token: &Token,
+        id: &str,
+    ) -> Result<(), &'static str> {
+        self.in_client(token, |client: &mut Client| client.banner = Some(id.to_owned()))
+    }
+
     pub(crate) fn sent_banner(
         &self,
         token: &Token,
+        id: &str,
     ) -> Result<(), &'static str> {
+        {
+            let mut banners = match self.banners.lock() {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            match banners.get_mut(id) {
+                Some(banner) => banner.sent += 1,
+                None => {
+                    banners.insert(id.to_owned(), BannerMetrics { sent: 1, ..BannerMetrics::default() });
+                },
+            }
+        }
         self.in_client(token, |client: &mut Client| client.sent_banners += 1)
     }
 }
 
 pub(crate) struct Token {
-    uid: usize,
+    uid:        usize,
+    generation: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overload::Overload;
+
+    #[test]
+    fn evicted_token_is_stale() {
+        let metrics = Metrics::new(Instant::now(), "fixed:16".to_owned(), None);
+        let limits = Limits::new(1, Overload::EvictOldest, None, None, 24, 64);
+        let peer = "192.0.2.1".parse().unwrap();
+        let (_, stale, _) = metrics.connect(&limits, Instant::now(), peer, Protocol::Ssh).unwrap();
+        let (connected, token, _) = metrics.connect(&limits, Instant::now(), peer, Protocol::Ssh).unwrap();
+        assert_eq!((connected, token.uid), (1, stale.uid));
+        assert_eq!(metrics.sent_chunk(&stale, 16), Err("Already Disconnected"));
+        assert!(metrics.disconnect(stale, "timeout").is_err());
+        assert_eq!(metrics.sent_chunk(&token, 16), Ok(()));
+        assert_eq!(metrics.connections(), 1);
+        assert_eq!(metrics.disconnect(token, "timeout").map(|(connected, _)| connected), Ok(0));
+    }
+
+    #[cfg(feature = "exporters")]
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape("fd3"), "fd3");
+        assert_eq!(escape("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+        let mut export = String::new();
+        labelled(&mut export, "test_total", "counter", "Test.", "name", vec![("x\"}\n", 1)]);
+        assert_eq!(export, "\n# HELP test_total Test.\n# TYPE test_total counter\ntest_total{name=\"x\\\"}\\n\"} 1\n");
+    }
 }
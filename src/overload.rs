@@ -0,0 +1,126 @@
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    time::Instant,
+};
+
+use super::address;
+
+/// What to do with a new connection, once `max_clients` is reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Overload {
+    /// Reject the new connection.
+    RejectNew,
+    /// Evict the oldest session.
+    EvictOldest,
+    /// Evict the oldest session of the network of peers holding the tarpit longest in total.
+    EvictLongestHeldPerPrefix,
+    /// Evict a random session.
+    EvictRandom,
+}
+
+impl Overload {
+    pub(crate) const VARIANTS: &'static [&'static str] = &[
+        "reject-new",
+        "evict-oldest",
+        "evict-longest-held-per-prefix",
+        "evict-random",
+    ];
+
+    /// Choose the session to evict of `sessions`, `(index, start, peer)`, returns its index.
+    /// Peers are grouped into networks of `ipv4_bits` or `ipv6_bits`.
+    pub(crate) fn victim<Sessions>(
+        self,
+        sessions: Sessions,
+        ipv4_bits: u8,
+        ipv6_bits: u8,
+    ) -> Option<usize>
+    where
+        Sessions: Iterator<Item = (usize, Instant, IpAddr)>,
+    {
+        match self {
+            Self::RejectNew => None,
+            Self::EvictOldest => sessions
+                .min_by_key(|(_, start, _)| *start)
+                .map(|(index, _, _)| index),
+            Self::EvictLongestHeldPerPrefix => {
+                let now = Instant::now();
+                let mut prefixes: HashMap<(IpAddr, u8), (f64, Instant, usize)> = HashMap::new();
+                for (index, start, peer) in sessions {
+                    let held = now.saturating_duration_since(start).as_secs_f64();
+                    let prefix = prefixes
+                        .entry(address::network(peer, ipv4_bits, ipv6_bits))
+                        .or_insert((0.0, start, index));
+                    prefix.0 += held;
+                    if start < prefix.1 {
+                        prefix.1 = start;
+                        prefix.2 = index;
+                    }
+                }
+                prefixes
+                    .values()
+                    .max_by(|left, right| left.0.total_cmp(&right.0))
+                    .map(|(_, _, index)| *index)
+            },
+            Self::EvictRandom => {
+                let sessions: Vec<usize> = sessions.map(|(index, _, _)| index).collect();
+                if sessions.is_empty() {
+                    None
+                } else {
+                    Some(sessions[rand::thread_rng().gen_range(0, sessions.len())])
+                }
+            },
+        }
+    }
+}
+
+impl FromStr for Overload {
+    type Err = String;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "reject-new"                    => Ok(Self::RejectNew),
+            "evict-oldest"                  => Ok(Self::EvictOldest),
+            "evict-longest-held-per-prefix" => Ok(Self::EvictLongestHeldPerPrefix),
+            "evict-random"                  => Ok(Self::EvictRandom),
+            _ => Err(format!("unknown overload policy: {}", policy)),
+        }
+    }
+}
+
+impl fmt::Display for Overload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::RejectNew                 => "reject-new",
+            Self::EvictOldest               => "evict-oldest",
+            Self::EvictLongestHeldPerPrefix => "evict-longest-held-per-prefix",
+            Self::EvictRandom               => "evict-random",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn evict_per_configured_prefix() {
+        let now = Instant::now();
+        let sessions = || vec![
+            (0, now - Duration::from_secs(30), "192.0.2.1".parse().unwrap()),
+            (1, now - Duration::from_secs(20), "192.0.3.1".parse().unwrap()),
+            (2, now - Duration::from_secs(25), "192.0.3.2".parse().unwrap()),
+        ].into_iter();
+        // Two networks of /24, 192.0.3.0/24 held the tarpit longest in total.
+        assert_eq!(Overload::EvictLongestHeldPerPrefix.victim(sessions(), 24, 64), Some(2));
+        // A single network of /16, its oldest session.
+        assert_eq!(Overload::EvictLongestHeldPerPrefix.victim(sessions(), 16, 64), Some(0));
+        assert_eq!(Overload::EvictOldest.victim(sessions(), 24, 64), Some(0));
+        assert_eq!(Overload::RejectNew.victim(sessions(), 24, 64), None);
+        assert_eq!(Overload::EvictRandom.victim(std::iter::empty(), 24, 64), None);
+    }
+}
@@ -0,0 +1,261 @@
+use rand::{
+    rngs::StdRng,
+    Rng,
+    SeedableRng,
+};
+use std::{
+    f64::consts::PI,
+    fmt,
+    net::SocketAddr,
+    str::FromStr,
+    time::Duration,
+};
+
+use super::adaptive::{Adaptive, AdaptiveSession};
+
+/// Longest delay a jitter-model may produce.
+const MAX_JITTER: f64 = 24.0 * 60.0 * 60.0;
+
+/// Longest duration accepted, so deadlines and timers never overflow an instant.
+pub(crate) const MAX_DURATION: Duration = Duration::from_secs(100 * 365 * 24 * 60 * 60);
+
+/// Parse durations like `10` (seconds), `250ms`, `1.5s` or `1m30s`, up to 100 years.
+pub(crate) fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    if let Ok(seconds) = text.parse::<u64>() {
+        return bounded(text, Duration::from_secs(seconds));
+    }
+
+    let mut total = 0f64;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let unit_end = rest[number_end..]
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .map_or(rest.len(), |end| number_end + end);
+        let number: f64 = rest[..number_end]
+            .parse()
+            .map_err(|_| format!("invalid duration: {}", text))?;
+        total += number * match &rest[number_end..unit_end] {
+            "ms"        => 0.001,
+            "s" | ""    => 1.0,
+            "m"         => 60.0,
+            "h"         => 60.0 * 60.0,
+            "d"         => 24.0 * 60.0 * 60.0,
+            unit => return Err(format!("invalid duration unit: {}", unit)),
+        };
+        rest = &rest[unit_end..];
+    }
+
+    if text.is_empty() {
+        return Err(format!("invalid duration: {}", text));
+    }
+    Duration::try_from_secs_f64(total)
+        .map_err(|err| format!("invalid duration: {}, error: {}", text, err))
+        .and_then(|duration| bounded(text, duration))
+}
+
+fn bounded(
+    text: &str,
+    duration: Duration,
+) -> Result<Duration, String> {
+    if duration > MAX_DURATION {
+        Err(format!("invalid duration: {}, error: \"longer than 100 years\"", text))
+    } else {
+        Ok(duration)
+    }
+}
+
+/// Random variation added to the delay before every chunk.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Jitter {
+    /// Always wait exactly the delay.
+    None,
+    /// `uniform:<min>-<max>`, uniformly distributed between `min` and `max`.
+    Uniform(Duration, Duration),
+    /// `exponential:<mean>`, exponentially distributed with mean `mean`.
+    Exponential(Duration),
+    /// `lognormal:<median>,<sigma>`, log-normally distributed with median `median`
+    /// and the standard deviation `sigma` of the logarithm.
+    LogNormal(Duration, f64),
+}
+
+impl FromStr for Jitter {
+    type Err = String;
+
+    fn from_str(jitter: &str) -> Result<Self, Self::Err> {
+        let (model, parameters) = match jitter.find(':') {
+            Some(colon) => (&jitter[..colon], &jitter[colon + 1..]),
+            None => (jitter, ""),
+        };
+        match model {
+            "none" => Ok(Self::None),
+            "uniform" => {
+                let dash = parameters
+                    .find('-')
+                    .ok_or_else(|| format!("expected uniform:<min>-<max>, got: {}", jitter))?;
+                let minimum = parse_duration(&parameters[..dash])?;
+                let maximum = parse_duration(&parameters[dash + 1..])?;
+                if minimum > maximum {
+                    Err(format!("minimum exceeds maximum: {}", jitter))
+                } else {
+                    Ok(Self::Uniform(minimum, maximum))
+                }
+            },
+            "exponential" => Ok(Self::Exponential(parse_duration(parameters)?)),
+            "lognormal" => {
+                let comma = parameters
+                    .find(',')
+                    .ok_or_else(|| format!("expected lognormal:<median>,<sigma>, got: {}", jitter))?;
+                let sigma: f64 = parameters[comma + 1..]
+                    .parse()
+                    .map_err(|_| format!("invalid sigma: {}", jitter))?;
+                if sigma.is_finite() && sigma >= 0.0 {
+                    Ok(Self::LogNormal(parse_duration(&parameters[..comma])?, sigma))
+                } else {
+                    Err(format!("invalid sigma: {}", jitter))
+                }
+            },
+            _ => Err(format!("unknown jitter: {}", jitter)),
+        }
+    }
+}
+
+impl fmt::Display for Jitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None                      => f.write_str("none"),
+            Self::Uniform(minimum, maximum) => write!(f, "uniform:{:?}-{:?}", minimum, maximum),
+            Self::Exponential(mean)         => write!(f, "exponential:{:?}", mean),
+            Self::LogNormal(median, sigma)  => write!(f, "lognormal:{:?},{}", median, sigma),
+        }
+    }
+}
+
+/// The base delay and jitter between two chunks.
+pub(crate) struct Pacing {
+    pub(crate) delay:     Duration,
+    pub(crate) jitter:    Jitter,
+    pub(crate) adaptive:  Option<Adaptive>,
+}
+
+impl Pacing {
+    /// A pacer with its own random number generator for a single connection.
+    pub(crate) fn pacer(
+        &self,
+        peer: &SocketAddr,
+    ) -> Pacer<'_> {
+        Pacer {
+            pacing:     self,
+            rng:        StdRng::from_rng(rand::thread_rng())
+                .unwrap_or_else(|_| StdRng::from_entropy()),
+            adaptive:   self.adaptive.as_ref().map(|adaptive| adaptive.session(peer, self.delay)),
+        }
+    }
+}
+
+impl fmt::Display for Pacing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "delay: {:.2?}, jitter: {}", self.delay, self.jitter)?;
+        if let Some(adaptive) = &self.adaptive {
+            write!(f, ", adaptive: {}", adaptive)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) struct Pacer<'a> {
+    pacing:     &'a Pacing,
+    rng:        StdRng,
+    adaptive:   Option<AdaptiveSession<'a>>,
+}
+
+impl Pacer<'_> {
+    /// The chunk was sent after the last delay.
+    pub(crate) fn sent(&mut self) {
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.held();
+        }
+    }
+
+    /// The client disconnected after waiting `waited` for the next chunk.
+    pub(crate) fn disconnected(
+        &self,
+        waited: Duration,
+    ) {
+        if let Some(adaptive) = &self.adaptive {
+            adaptive.gave_up(waited);
+        }
+    }
+
+    /// The time to wait before the next chunk.
+    pub(crate) fn next_delay(&mut self) -> Duration {
+        let delay = self.adaptive.as_ref().map_or(self.pacing.delay, AdaptiveSession::delay);
+        let jitter = match self.pacing.jitter {
+            Jitter::None => 0.0,
+            Jitter::Uniform(minimum, maximum) => {
+                let (minimum, maximum) = (minimum.as_secs_f64(), maximum.as_secs_f64());
+                minimum + (maximum - minimum) * self.rng.gen::<f64>()
+            },
+            Jitter::Exponential(mean) => {
+                -mean.as_secs_f64() * (1.0 - self.rng.gen::<f64>()).ln()
+            },
+            Jitter::LogNormal(median, sigma) => {
+                // Box-Muller transform for a standard normal sample.
+                let radius = (-2.0 * (1.0 - self.rng.gen::<f64>()).ln()).sqrt();
+                let normal = radius * (2.0 * PI * self.rng.gen::<f64>()).cos();
+                median.as_secs_f64() * (sigma * normal).exp()
+            },
+        };
+        delay
+            .checked_add(Duration::from_secs_f64(jitter.clamp(0.0, MAX_JITTER)))
+            .map_or(MAX_DURATION, |delay| delay.min(MAX_DURATION))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("10"), Ok(Duration::from_secs(10)));
+        assert_eq!(parse_duration(" 250ms "), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("1.5s"), Ok(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("1m30s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("1d2h"), Ok(Duration::from_secs(26 * 60 * 60)));
+        assert_eq!(parse_duration("36500d"), Ok(MAX_DURATION));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for text in &["", "s", "1x", "1..5s", "-1s", "36501d", "18446744073709551615", "18446744073709551615s", "99999999999999999999d"] {
+            assert!(parse_duration(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn jitters() {
+        assert_eq!("none".parse(), Ok(Jitter::None));
+        assert_eq!(
+            "uniform:1s-2s".parse(),
+            Ok(Jitter::Uniform(Duration::from_secs(1), Duration::from_secs(2))),
+        );
+        assert!("uniform:2s-1s".parse::<Jitter>().is_err());
+        assert!("lognormal:1s,-1".parse::<Jitter>().is_err());
+        assert!("lognormal:1s,NaN".parse::<Jitter>().is_err());
+    }
+
+    #[test]
+    fn saturate_delay() {
+        let pacing = Pacing {
+            delay:    MAX_DURATION,
+            jitter:   Jitter::Uniform(Duration::from_secs(1), Duration::from_secs(2)),
+            adaptive: None,
+        };
+        let peer = "127.0.0.1:22".parse().unwrap();
+        assert_eq!(pacing.pacer(&peer).next_delay(), MAX_DURATION);
+    }
+}
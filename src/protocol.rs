@@ -0,0 +1,118 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    str::FromStr,
+};
+
+/// The kind of tarpit served on a listener.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Protocol {
+    /// Endless lines before the SSH version string.
+    Ssh,
+    /// Endless headers after `HTTP/1.1 200 OK`.
+    Http,
+    /// An endless multi-line `220-` greeting.
+    Smtp,
+    /// Endless telnet option negotiations and login prompts.
+    Telnet,
+    /// Guess the protocol from the first bytes sent by the client.
+    /// SMTP clients wait for the greeting of the server, so they need a `smtp://` listener instead.
+    Sniff,
+}
+
+impl Protocol {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["ssh", "http", "smtp", "telnet", "sniff"];
+}
+
+impl FromStr for Protocol {
+    type Err = String;
+
+    fn from_str(protocol: &str) -> Result<Self, Self::Err> {
+        match protocol {
+            "ssh"    => Ok(Self::Ssh),
+            "http"   => Ok(Self::Http),
+            "smtp"   => Ok(Self::Smtp),
+            "telnet" => Ok(Self::Telnet),
+            "sniff"  => Ok(Self::Sniff),
+            _ => Err(format!("unknown protocol: {}, expected one of: {}", protocol, Self::VARIANTS.join(", "))),
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ssh    => "ssh",
+            Self::Http   => "http",
+            Self::Smtp   => "smtp",
+            Self::Telnet => "telnet",
+            Self::Sniff  => "sniff",
+        })
+    }
+}
+
+/// Guess the protocol from the first bytes sent by a client,
+/// returns the protocol to serve and the name of the detected protocol.
+pub(crate) fn sniff(bytes: &[u8]) -> (Protocol, &'static str) {
+    const HTTP_METHODS: &[&[u8]] = &[
+        b"GET ", b"HEAD", b"POST", b"PUT ", b"DELE", b"OPTI", b"CONN", b"TRAC", b"PATC", b"PRI ",
+    ];
+
+    let prefix = &bytes[..bytes.len().min(4)];
+    if prefix == b"SSH-" {
+        (Protocol::Ssh, "ssh")
+    } else if HTTP_METHODS.contains(&prefix) {
+        (Protocol::Http, "http")
+    } else if bytes.len() >= 2 && bytes[0] == 0x16 && bytes[1] == 0x03 {
+        (Protocol::Ssh, "tls")
+    } else if bytes.first() == Some(&0xff) {
+        (Protocol::Telnet, "telnet")
+    } else {
+        (Protocol::Ssh, "unknown")
+    }
+}
+
+/// A listen address with an optional protocol, e.g. `0.0.0.0:2222` or `http://0.0.0.0:8081`.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ListenAddress {
+    pub(crate) protocol:  Protocol,
+    pub(crate) address:   SocketAddr,
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        let (protocol, address) = match address.find("://") {
+            Some(separator) => (address[..separator].parse()?, &address[separator + 3..]),
+            None => (Protocol::Ssh, address),
+        };
+        Ok(Self {
+            protocol,
+            address: address
+                .parse()
+                .map_err(|err| format!("invalid address: {}, error: {}", address, err))?,
+        })
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}", self.protocol, self.address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniff_first_bytes() {
+        assert_eq!(sniff(b"SSH-2.0-"), (Protocol::Ssh, "ssh"));
+        assert_eq!(sniff(b"GET / HT"), (Protocol::Http, "http"));
+        assert_eq!(sniff(&[0x16, 0x03, 0x01]), (Protocol::Ssh, "tls"));
+        assert_eq!(sniff(&[0xff, 0xfd, 0x18]), (Protocol::Telnet, "telnet"));
+        assert_eq!(sniff(b"EHLO exa"), (Protocol::Ssh, "unknown"));
+        assert_eq!(sniff(b""), (Protocol::Ssh, "unknown"));
+    }
+}
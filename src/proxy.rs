@@ -0,0 +1,202 @@
+use futures::future::{select, Either};
+use log::{info, warn};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+    time::{delay_for, timeout},
+};
+
+use super::{
+    access_list::{AccessLists, ListFile},
+    metrics::Metrics,
+};
+
+/// Time to connect to the upstream within.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size of the buffer of each direction.
+const BUFFER_SIZE: usize = 8192;
+
+/// Passes trusted clients through to a real server.
+pub(crate) struct Proxy {
+    upstream:     SocketAddr,
+    trusted:      AccessLists,
+    /// Time without bytes in either direction, before closing a session.
+    idle_timeout: Option<Duration>,
+}
+
+impl Proxy {
+    /// Pass clients on the networks of the `trusted` files through to `upstream`.
+    pub(crate) fn new(
+        upstream: SocketAddr,
+        trusted: Vec<ListFile>,
+        idle_timeout: Option<Duration>,
+    ) -> Result<Self, String> {
+        Ok(Self {
+            upstream,
+            trusted: AccessLists::new(trusted, Vec::new())?,
+            idle_timeout,
+        })
+    }
+
+    /// Reload the trusted networks on every SIGHUP.
+    pub(crate) fn spawn_reload(
+        self: &Arc<Self>,
+        runtime: &tokio::runtime::Runtime,
+    ) {
+        let proxy = self.clone();
+        runtime.spawn(async move {
+            proxy.trusted.reload_on_hangup().await;
+        });
+    }
+
+    /// Whether to pass the client at `ip` to a listener on `port` through.
+    pub(crate) fn trusts(
+        &self,
+        ip: IpAddr,
+        port: u16,
+    ) -> bool {
+        self.trusted.allows(ip, port)
+    }
+
+    /// Connect `sock` of `peer` to the upstream and copy in both directions,
+    /// until both sides are closed or the session is idle for too long.
+    pub(crate) async fn splice(
+        self: Arc<Self>,
+        mut sock: TcpStream,
+        peer: SocketAddr,
+        metrics: Arc<Metrics>,
+    ) {
+        let start = Instant::now();
+        metrics.proxy_started();
+        info!("proxy, peer: {}, upstream: {}, proxied: {}", peer, self.upstream, metrics.proxied());
+        let mut upstream = match timeout(CONNECT_TIMEOUT, TcpStream::connect(self.upstream)).await {
+            Ok(Ok(upstream)) => upstream,
+            Ok(Err(err)) => return self.failed(&metrics, peer, start, &err.to_string()),
+            Err(_) => return self.failed(&metrics, peer, start, "connect time out"),
+        };
+        sock.set_nodelay(true)
+            .and_then(|_| upstream.set_nodelay(true))
+            .unwrap_or_else(|err| warn!("set_nodelay(), error: {}", err));
+
+        let activity = Activity { start, last: AtomicU64::new(0) };
+        let sent = AtomicU64::new(0);
+        let received = AtomicU64::new(0);
+        let (mut client_read, mut client_write) = sock.split();
+        let (mut upstream_read, mut upstream_write) = upstream.split();
+        let copy = futures::future::join(
+            forward(&mut client_read, &mut upstream_write, &sent, &activity),
+            forward(&mut upstream_read, &mut client_write, &received, &activity),
+        );
+        let idle = activity.idle(self.idle_timeout);
+        futures::pin_mut!(copy);
+        futures::pin_mut!(idle);
+        let error = match select(copy, idle).await {
+            Either::Left(((Err(err), _), _)) | Either::Left(((_, Err(err)), _)) => Some(err.to_string()),
+            Either::Left(_) => None,
+            Either::Right(_) => Some("idle time out".to_owned()),
+        };
+        let sent = sent.load(Ordering::Relaxed);
+        let received = received.load(Ordering::Relaxed);
+        metrics.proxy_finished(sent, received, error.is_some());
+        info!(
+            "proxy end, peer: {}, duration: {:.2?}, sent: {}, received: {}, error: \"{}\"",
+            peer,
+            start.elapsed(),
+            sent,
+            received,
+            error.unwrap_or_default(),
+        );
+    }
+
+    fn failed(
+        &self,
+        metrics: &Metrics,
+        peer: SocketAddr,
+        start: Instant,
+        error: &str,
+    ) {
+        metrics.proxy_finished(0, 0, true);
+        warn!("proxy end, peer: {}, duration: {:.2?}, upstream: {}, error: \"{}\"", peer, start.elapsed(), self.upstream, error);
+    }
+}
+
+impl fmt::Display for Proxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "upstream: {}, trusted: [{}], idle_timeout: {:.2?}",
+            self.upstream,
+            self.trusted.allow_files(),
+            self.idle_timeout,
+        )
+    }
+}
+
+/// When a session last received bytes from either side.
+struct Activity {
+    start:  Instant,
+    /// Milliseconds since `start`.
+    last:   AtomicU64,
+}
+
+impl Activity {
+    fn received(&self) {
+        self.last.store(self.start.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Complete once nothing was received for `idle_timeout`, never without.
+    async fn idle(
+        &self,
+        idle_timeout: Option<Duration>,
+    ) {
+        let idle_timeout = match idle_timeout {
+            Some(idle_timeout) => idle_timeout,
+            None => return futures::future::pending().await,
+        };
+        loop {
+            let idle = (self.start + Duration::from_millis(self.last.load(Ordering::Relaxed))).elapsed();
+            if idle >= idle_timeout {
+                return;
+            }
+            delay_for(idle_timeout - idle).await;
+        }
+    }
+}
+
+/// Copy until the end of `reader` or an error, counting the bytes in `copied`,
+/// then close the write side of `writer`.
+async fn forward<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    copied: &AtomicU64,
+    activity: &Activity,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buffer = vec![0u8; BUFFER_SIZE];
+    let copying = async {
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            activity.received();
+            writer.write_all(&buffer[..read]).await?;
+            copied.fetch_add(read as u64, Ordering::Relaxed);
+        }
+    }.await;
+    let closed = writer.shutdown().await;
+    copying.and(closed)
+}
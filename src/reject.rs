@@ -0,0 +1,138 @@
+use log::debug;
+use std::{
+    fmt,
+    net::{Shutdown, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::TcpStream,
+    time::{delay_for, timeout},
+};
+
+use super::{
+    metrics::Metrics,
+    protocol::Protocol,
+};
+
+/// Time to send the busy banner within.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How to get rid of connections beyond max-clients.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum RejectMode {
+    /// Reset the connection.
+    Rst,
+    /// Close the connection gracefully.
+    Fin,
+    /// Tell the client, that the server is busy, and close the connection.
+    Busy,
+    /// Keep the connection open without reading or writing.
+    Hold,
+}
+
+impl RejectMode {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["rst", "fin", "busy", "hold"];
+}
+
+impl FromStr for RejectMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "rst"   => Ok(Self::Rst),
+            "fin"   => Ok(Self::Fin),
+            "busy"  => Ok(Self::Busy),
+            "hold"  => Ok(Self::Hold),
+            _ => Err(format!("unknown reject mode: {}", mode)),
+        }
+    }
+}
+
+impl fmt::Display for RejectMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Rst   => "rst",
+            Self::Fin   => "fin",
+            Self::Busy  => "busy",
+            Self::Hold  => "hold",
+        })
+    }
+}
+
+/// The busy banner of `protocol`.
+fn busy(protocol: Protocol) -> &'static [u8] {
+    match protocol {
+        Protocol::Http => b"HTTP/1.1 503 Service Unavailable\r\nConnection: close\r\nContent-Length: 0\r\n\r\n",
+        Protocol::Smtp => b"421 Service not available, try again later\r\n",
+        Protocol::Ssh | Protocol::Telnet | Protocol::Sniff => b"Server busy, try again later\r\n",
+    }
+}
+
+/// How to reject connections beyond max-clients.
+pub(crate) struct Reject {
+    pub(crate) mode:      RejectMode,
+    /// Time to hold connections with the hold mode.
+    pub(crate) hold:      Duration,
+    /// Connections to hold at most, before resetting them instead.
+    pub(crate) max_held:  usize,
+}
+
+impl Reject {
+    /// Get rid of the connection `sock` of `peer` to a listener of `protocol`, returns the mode used.
+    pub(crate) fn reject(
+        &self,
+        sock: TcpStream,
+        peer: SocketAddr,
+        protocol: Protocol,
+        metrics: &Arc<Metrics>,
+    ) -> RejectMode {
+        let mode = if self.mode == RejectMode::Hold && !metrics.hold(self.max_held) {
+            RejectMode::Rst
+        } else {
+            self.mode
+        };
+        metrics.rejected(mode);
+        match mode {
+            RejectMode::Rst => sock
+                .set_linger(Some(Duration::from_secs(0)))
+                .unwrap_or_else(|err| debug!("set_linger(), peer: {}, error: {}", peer, err)),
+            RejectMode::Fin => sock
+                .shutdown(Shutdown::Both)
+                .unwrap_or_else(|err| debug!("shutdown(), peer: {}, error: {}", peer, err)),
+            RejectMode::Busy => {
+                let mut sock = sock;
+                tokio::spawn(async move {
+                    match timeout(BUSY_TIMEOUT, sock.write_all(busy(protocol))).await {
+                        Ok(Ok(_)) => sock
+                            .shutdown(Shutdown::Both)
+                            .unwrap_or_else(|err| debug!("shutdown(), peer: {}, error: {}", peer, err)),
+                        Ok(Err(err)) => debug!("busy, peer: {}, error: {}", peer, err),
+                        Err(_) => debug!("busy, peer: {}, error: time out", peer),
+                    }
+                });
+            },
+            RejectMode::Hold => {
+                let hold = self.hold;
+                let metrics = metrics.clone();
+                tokio::spawn(async move {
+                    delay_for(hold).await;
+                    drop(sock);
+                    metrics.released();
+                });
+            },
+        }
+        mode
+    }
+}
+
+impl fmt::Display for Reject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.mode {
+            RejectMode::Hold => write!(f, "{}, hold: {:.2?}, max_held: {}", self.mode, self.hold, self.max_held),
+            mode => write!(f, "{}", mode),
+        }
+    }
+}
@@ -1,4 +1,3 @@
-use exitcode;
 use futures::stream::StreamExt;
 use futures_util::future::FutureExt;
 use log::info;
@@ -25,7 +24,7 @@ impl Runtime {
         let scheduler = if let Some(threaded) = threads {
             runtime.threaded_scheduler();
             if let Some(threads) = threaded {
-                let threads = threads.min(512).max(1);
+                let threads = threads.clamp(1, 512);
                 runtime.core_threads(threads);
                 format!("threaded, threads: {}", threads)
             } else {
@@ -1,146 +1,736 @@
 use log::info;
 use std::{
     borrow::Cow,
+    fmt,
+    io,
     net::SocketAddr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use log::warn;
-use tokio::io::AsyncWriteExt;
+use futures::future::{select, Either};
+use log::{debug, warn};
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::oneshot;
 use tokio::time::{delay_for, timeout};
 
-use super::metrics::{Metrics, Token};
+use super::{
+    banner::{Banner, Banners, Rotating},
+    chunking::Chunking,
+    easter_egg::EasterEggs,
+    identification::Identification,
+    kex::{self, ClientKexInit, KexInitParser},
+    metrics::{Metrics, Token},
+    pacing::{Pacer, Pacing},
+    protocol::{self, Protocol},
+    template::{self, Variables},
+};
+#[cfg(feature = "honeypot")]
+use super::honeypot::{Honeypot, Transport};
 
-async fn send_chunk(
-    sock: &mut tokio::net::TcpStream,
-    delay: &Duration,
-    time_out: &Duration,
-    token: Token,
-    metrics: &Arc<Metrics>,
-    chunk: &[u8],
-) -> Result<Token, (usize, u64, Cow<'static, str>)> {
-    delay_for(*delay).await;
-    match timeout(
-        *time_out,
-        sock.write_all(chunk)
-    )
-    .await {
-        Ok(Ok(_)) => if let Err(error) = metrics.sent_chunk(&token) {
-            Err(match metrics.disconnect(token) {
-                Ok((connections, connection_time)) => (
-                    connections,
-                    connection_time,
-                    Cow::Borrowed(error),
-                ),
-                Err(failure) => (
-                    0usize,
-                    0u64,
-                    Cow::Owned(format!("{}\", \"{}", error, failure)),
-                ),
-            })
+/// Everything a tarpitted connection needs to know, shared by all connections.
+pub(crate) struct Tarpit {
+    pub(crate) pacing:            Pacing,
+    pub(crate) time_out:          Duration,
+    pub(crate) chunking:          Chunking,
+    pub(crate) send_buffer_size:  usize,
+    /// Maximum length of a line in bytes, including CRLF.
+    pub(crate) max_line_length:   usize,
+    pub(crate) keepalive:         Option<Duration>,
+    pub(crate) user_timeout:      Option<Duration>,
+    pub(crate) sniff_timeout:     Duration,
+    pub(crate) kex_after:         Option<usize>,
+    pub(crate) max_duration:      Option<Duration>,
+    pub(crate) max_bytes:         Option<usize>,
+    pub(crate) goodbye:           Option<Vec<u8>>,
+    pub(crate) banners:           Banners,
+    pub(crate) easter_eggs:       EasterEggs,
+    #[cfg(feature = "honeypot")]
+    pub(crate) honeypot:          Option<Honeypot>,
+}
+
+impl fmt::Display for Tarpit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, timeout: {:.2?}, keepalive: {:.2?}, user_timeout: {:.2?}, sniff_timeout: {:.2?}, kex_after: {:?}, max_duration: {:.2?}, max_bytes: {:?}, goodbye: {}, chunking: {}, easter_eggs: {}, {}",
+            self.pacing,
+            self.time_out,
+            self.keepalive,
+            self.user_timeout,
+            self.sniff_timeout,
+            self.kex_after,
+            self.max_duration,
+            self.max_bytes,
+            self.goodbye.is_some(),
+            self.chunking,
+            self.easter_eggs,
+            self.banners,
+        )
+    }
+}
+
+/// Abort the connection, if sent data stays unacknowledged for `user_timeout`.
+#[cfg(target_os = "linux")]
+fn set_user_timeout(
+    sock: &tokio::net::TcpStream,
+    user_timeout: Duration,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let milliseconds = user_timeout.as_millis().min(libc::c_uint::MAX as u128) as libc::c_uint;
+    let result = unsafe {
+        libc::setsockopt(
+            sock.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_USER_TIMEOUT,
+            &milliseconds as *const libc::c_uint as *const libc::c_void,
+            std::mem::size_of::<libc::c_uint>() as libc::socklen_t,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_user_timeout(
+    _sock: &tokio::net::TcpStream,
+    _user_timeout: Duration,
+) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "TCP_USER_TIMEOUT is not supported"))
+}
+
+/// Classify socket errors for the metrics.
+pub(crate) fn reason(error: &io::Error) -> &'static str {
+    match error.kind() {
+        io::ErrorKind::ConnectionReset    => "reset",
+        io::ErrorKind::ConnectionAborted  => "aborted",
+        io::ErrorKind::BrokenPipe         => "broken pipe",
+        io::ErrorKind::TimedOut           => "user timeout",
+        _                                 => "error",
+    }
+}
+
+/// Number of remaining clients, connection time and error of a closed session.
+type Disconnect = (usize, u64, Cow<'static, str>);
+
+/// A single tarpitted connection.
+struct Session<'a> {
+    sock:           tokio::net::TcpStream,
+    peer:           SocketAddr,
+    id:             String,
+    start:          Instant,
+    deadline:       Option<Instant>,
+    sent_bytes:     usize,
+    /// Whether the delay before the next chunk already passed.
+    paused:         bool,
+    protocol:       Protocol,
+    metrics:        &'a Metrics,
+    tarpit:         &'a Tarpit,
+    pacer:          Pacer<'a>,
+    identification: Identification,
+    kexinit:        KexInitParser,
+    class:          &'static str,
+    stage:          &'static str,
+    stage_start:    Instant,
+}
+
+impl<'a> Session<'a> {
+    /// Unregister the client and prepare the log-entry.
+    fn disconnect(
+        &self,
+        token: Token,
+        reason: &'static str,
+        error: Cow<'static, str>,
+    ) -> Disconnect {
+        self.metrics.spent(self.stage, self.stage_start.elapsed());
+        match self.metrics.disconnect(token, reason) {
+            Ok((connections, connection_time)) => (
+                connections,
+                connection_time,
+                error,
+            ),
+            Err(failure) => (
+                0usize,
+                0u64,
+                Cow::Owned(format!("{}\", \"{}", error, failure)),
+            ),
+        }
+    }
+
+    /// The limit of the session reached before sending `length` more bytes, if any.
+    fn exceeded(
+        &self,
+        length: usize,
+    ) -> Option<(&'static str, &'static str)> {
+        if self.deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+            Some(("max duration", "maximum session duration reached"))
+        } else if self.tarpit.max_bytes.map_or(false, |max_bytes| self.sent_bytes + length > max_bytes) {
+            Some(("max bytes", "maximum session bytes reached"))
         } else {
-            Ok(token)
-        },
-        Err(error) => {
-          Err(match metrics.disconnect(token) {
-              Ok((connections, connection_time)) => (
-                  connections,
-                  connection_time,
-                  Cow::Borrowed("time out"),
-              ),
-              Err(failure) => (
-                  0usize,
-                  0u64,
-                  Cow::Owned(format!("{}\", \"{}", error, failure)),
-              ),
-          })
+            None
+        }
+    }
+
+    /// Say goodbye, unless the key exchange started, and unregister the client.
+    async fn goodbye(
+        &mut self,
+        token: Token,
+        (reason, error): (&'static str, &'static str),
+    ) -> Disconnect {
+        if let (Some(goodbye), "banner") = (&self.tarpit.goodbye, self.stage) {
+            let line = farewell(self.protocol, &template::expand(goodbye, &self.variables()));
+            match timeout(self.tarpit.time_out, self.sock.write_all(&line)).await {
+                Ok(Ok(_)) => self.metrics.sent_chunk(&token, line.len())
+                    .unwrap_or_else(|err| warn!("sent_chunk(), error: {}", err)),
+                Ok(Err(err)) => debug!("goodbye, peer: {}, error: {}", self.peer, err),
+                Err(_) => debug!("goodbye, peer: {}, error: time out", self.peer),
+            }
+        }
+        self.disconnect(token, reason, Cow::Borrowed(error))
+    }
+
+    /// The metrics lost track of the client.
+    fn failed(
+        &self,
+        error: &'static str,
+    ) -> Disconnect {
+        (0usize, 0u64, Cow::Borrowed(error))
+    }
+
+    fn log(
+        &self,
+        (connected, connection_time, error): Disconnect,
+    ) {
+        info!(
+            "disconnect, peer: {}, session: {}, protocol: {}, duration: {:.2?}, error: \"{}\", class: {}, clients: {}",
+            self.peer,
+            self.id,
+            self.protocol,
+            connection_time,
+            error,
+            self.class,
+            connected,
+        );
+    }
+
+    /// Choose the banner of this connection.
+    fn start_banner(
+        &self,
+        token: &Token,
+    ) -> Result<Rotating<'a>, Disconnect> {
+        let banner = self.tarpit.banners.start(self.class);
+        self.metrics.showing(token, banner.id()).map_err(|error| self.failed(error))?;
+        Ok(banner)
+    }
+
+    /// Count the completed banner-loop and maybe switch to another banner.
+    fn sent_banner(
+        &self,
+        token: &Token,
+        banner: &mut Rotating<'_>,
+    ) -> Result<(), Disconnect> {
+        self.metrics.sent_banner(token, banner.id()).map_err(|error| self.failed(error))?;
+        if banner.rotate(self.class) {
+            self.metrics.showing(token, banner.id()).map_err(|error| self.failed(error))?;
+        }
+        Ok(())
+    }
+
+    /// The values of the placeholders of the banner right now.
+    fn variables(&self) -> Variables<'_> {
+        Variables {
+            peer:           self.peer,
+            session_id:     &self.id,
+            uptime:         self.metrics.uptime(),
+            connected_for:  self.start.elapsed(),
+            clients:        self.metrics.connections(),
+            max_length:     self.tarpit.max_line_length,
+        }
+    }
+
+    /// Switch to the next stage of the tarpit.
+    fn enter(
+        &mut self,
+        stage: &'static str,
+    ) {
+        self.metrics.spent(self.stage, self.stage_start.elapsed());
+        debug!("stage, peer: {}, from: {}, to: {}", self.peer, self.stage, stage);
+        self.stage = stage;
+        self.stage_start = Instant::now();
+    }
+
+    /// Capture the identification string and the KEXINIT of the client from `bytes` read.
+    fn received(
+        &mut self,
+        token: &Token,
+        bytes: &[u8],
+    ) {
+        let (class, rest) = self.identification.feed(bytes);
+        if let Some(class) = class {
+            self.class = class;
+            info!(
+                "identified, peer: {}, class: {}, version: {:?}",
+                self.peer,
+                class,
+                self.identification.version().unwrap_or_default(),
+            );
+            self.metrics.identified(token, class)
+                .unwrap_or_else(|err| warn!("identified(), error: {}", err));
+        }
+        match self.kexinit.feed(rest) {
+            Some(Ok(kexinit)) => self.fingerprint(&kexinit),
+            Some(Err(error)) => debug!("kexinit, peer: {}, error: \"{}\"", self.peer, error),
+            None => (),
+        }
+    }
+
+    /// Record the HASSH-fingerprint of the KEXINIT of the client.
+    fn fingerprint(
+        &self,
+        kexinit: &ClientKexInit,
+    ) {
+        let hassh = kexinit.hassh();
+        info!(
+            "kexinit, peer: {}, hassh: {}, algorithms: \"{}\", host_key: \"{}\"",
+            self.peer,
+            hassh,
+            kexinit.hassh_algorithms(),
+            kexinit.host_key,
+        );
+        self.metrics.fingerprinted(hassh);
+    }
+
+    /// Read once from the client within the timeout.
+    #[cfg(feature = "honeypot")]
+    async fn read(
+        &mut self,
+        token: &Token,
+    ) -> Result<(), (&'static str, Cow<'static, str>)> {
+        let mut buffer = [0u8; 256];
+        match timeout(self.tarpit.time_out, self.sock.read(&mut buffer)).await {
+            Err(_) => Err(("time out", Cow::Borrowed("time out"))),
+            Ok(Ok(0)) => Err(("eof", Cow::Borrowed("end of file"))),
+            Ok(Ok(read)) => {
+                self.received(token, &buffer[..read]);
+                Ok(())
+            },
+            Ok(Err(error)) => Err((reason(&error), Cow::Owned(format!("{}", error)))),
+        }
+    }
+
+    /// Wait for `delay`, while watching the read side for the client to disconnect.
+    async fn wait(
+        &mut self,
+        token: &Token,
+        delay: Duration,
+    ) -> Result<(), (&'static str, Cow<'static, str>)> {
+        let mut buffer = [0u8; 64];
+        let mut sleep = delay_for(delay);
+        loop {
+            match select(sleep, self.sock.read(&mut buffer)).await {
+                Either::Left(_) => return Ok(()),
+                Either::Right((Ok(0), _)) => return Err(("eof", Cow::Borrowed("end of file"))),
+                Either::Right((Ok(read), pending)) => {
+                    sleep = pending;
+                    self.received(token, &buffer[..read]);
+                },
+                Either::Right((Err(error), _)) => return Err((reason(&error), Cow::Owned(format!("{}", error)))),
+            }
+        }
+    }
+
+    /// Wait for the delay before the next chunk, capped at the deadline.
+    async fn pause(
+        &mut self,
+        token: Token,
+    ) -> Result<Token, Disconnect> {
+        let waiting = Instant::now();
+        let delay = match self.deadline {
+            Some(deadline) => self.pacer.next_delay().min(deadline.saturating_duration_since(waiting)),
+            None => self.pacer.next_delay(),
+        };
+        if let Err((reason, error)) = self.wait(&token, delay).await {
+            self.pacer.disconnected(waiting.elapsed());
+            return Err(self.disconnect(token, reason, error));
+        }
+        Ok(token)
+    }
+
+    /// Wait for the delay before the first chunk already, as most clients send their identification string
+    /// right away, so the banner can be chosen for the class of the client.
+    async fn await_identification(
+        &mut self,
+        token: Token,
+    ) -> Result<Token, Disconnect> {
+        let token = self.pause(token).await?;
+        self.paused = true;
+        Ok(token)
+    }
+
+    async fn send_chunk(
+        &mut self,
+        mut token: Token,
+        chunk: &[u8],
+    ) -> Result<Token, Disconnect> {
+        let waiting = Instant::now();
+        if !std::mem::replace(&mut self.paused, false) {
+            token = self.pause(token).await?;
+        }
+        if let Some(limit) = self.exceeded(chunk.len()) {
+            return Err(self.goodbye(token, limit).await);
+        }
+        match timeout(
+            self.tarpit.time_out,
+            self.sock.write_all(chunk)
+        )
+        .await {
+            Ok(Ok(_)) => if let Err(error) = self.metrics.sent_chunk(&token, chunk.len()) {
+                Err(self.disconnect(token, "error", Cow::Borrowed(error)))
+            } else {
+                self.sent_bytes += chunk.len();
+                self.pacer.sent();
+                Ok(token)
+            },
+            Err(_) => {
+                self.pacer.disconnected(waiting.elapsed());
+                Err(self.disconnect(token, "time out", Cow::Borrowed("time out")))
+            },
+            Ok(Err(error)) => {
+                self.pacer.disconnected(waiting.elapsed());
+                Err(self.disconnect(token, reason(&error), Cow::Owned(format!("{}", error))))
+            },
+        }
+    }
+
+    /// Wait up to `sniff_timeout` for the first bytes of the client to choose the protocol.
+    /// The bytes are only peeked at and remain to be read.
+    async fn sniff(
+        &mut self,
+        token: Token,
+    ) -> Result<(Protocol, Token), Disconnect> {
+        let deadline = Instant::now() + self.tarpit.sniff_timeout;
+        let mut buffer = [0u8; 8];
+        let mut peeked = 0;
+        let detected = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match timeout(remaining, self.sock.peek(&mut buffer)).await {
+                Err(_) => break protocol::sniff(&buffer[..peeked]),
+                Ok(Ok(0)) => return Err(self.disconnect(token, "eof", Cow::Borrowed("end of file"))),
+                Ok(Ok(read)) if read >= 4 || buffer[..read].contains(&b'\n') || remaining == Duration::from_secs(0) => {
+                    break protocol::sniff(&buffer[..read]);
+                },
+                Ok(Ok(read)) => {
+                    peeked = read;
+                    delay_for(Duration::from_millis(10)).await;
+                },
+                Ok(Err(error)) => return Err(self.disconnect(token, reason(&error), Cow::Owned(format!("{}", error)))),
+            }
+        };
+        let (protocol, name) = detected;
+        info!("sniffed, peer: {}, detected: {}, protocol: {}", self.peer, name, protocol);
+        self.metrics.sniffed(name);
+        Ok((protocol, token))
+    }
+
+    /// Send `line` chunk by chunk.
+    async fn send_line(
+        &mut self,
+        mut token: Token,
+        line: &[u8],
+    ) -> Result<Token, Disconnect> {
+        for chunk in self.tarpit.chunking.split(line) {
+            token = self.send_chunk(token, chunk).await?;
+        }
+        Ok(token)
+    }
+}
+
+/// Waffle on endlessly before the SSH version string.
+async fn ssh(
+    session: &mut Session<'_>,
+    mut token: Token,
+) -> Result<(), Disconnect> {
+    #[cfg(feature = "honeypot")]
+    let after = session.tarpit.honeypot.as_ref().map(Honeypot::after).or(session.tarpit.kex_after);
+    #[cfg(not(feature = "honeypot"))]
+    let after = session.tarpit.kex_after;
+
+    let tarpit = session.tarpit;
+    token = session.await_identification(token).await?;
+    let mut lines = session.start_banner(&token)?;
+    let mut rounds = 0;
+    while after != Some(rounds) {
+        if let Some(egg) = tarpit.easter_eggs.choose() {
+            let line = template::expand(&egg.line, &session.variables());
+            token = session.send_chunk(token, &line).await?;
+            session.metrics.sent_easteregg(&token, &egg.id).map_err(|error| session.failed(error))?;
+        }
+
+        while let Some(line) = lines.next_line() {
+            let line = template::expand(&line, &session.variables()).into_owned();
+            token = session.send_line(token, &line).await?;
+        }
+
+        session.sent_banner(&token, &mut lines)?;
+        rounds += 1;
+    }
+
+    #[cfg(feature = "honeypot")]
+    {
+        if let Some(honeypot) = &tarpit.honeypot {
+            return ssh_honeypot(session, token, honeypot).await;
+        }
+    }
+
+    session.enter("kex");
+    token = session.send_line(token, kex::VERSION).await?;
+    token = session.send_line(token, &kex::kexinit()).await?;
+    loop {
+        token = session.send_line(token, &kex::ignore()).await?;
+    }
+}
+
+/// Exchange keys for real and record every login attempt.
+#[cfg(feature = "honeypot")]
+async fn ssh_honeypot(
+    session: &mut Session<'_>,
+    token: Token,
+    honeypot: &Honeypot,
+) -> Result<(), Disconnect> {
+    session.enter("honeypot");
+    let deadline = session.deadline;
+    let exchange = async {
+        let mut transport = Transport::new(session.tarpit.time_out);
+        transport.write(&mut session.sock, kex::VERSION).await?;
+        let version = loop {
+            match session.identification.version() {
+                Some(version) => break version,
+                None => session.read(&token).await?,
+            }
+        };
+        let (pending, reported) = session.kexinit.take();
+        transport.resume(pending);
+        let kexinit = honeypot.key_exchange(&mut transport, &mut session.sock, version.as_bytes()).await?;
+        if !reported {
+            session.fingerprint(&kexinit);
+        }
+        honeypot.authenticate(&mut transport, &mut session.sock, &session.peer, session.metrics).await
+    };
+    let result = match deadline {
+        Some(deadline) => timeout(deadline.saturating_duration_since(Instant::now()), exchange)
+            .await
+            .unwrap_or(Err(("max duration", Cow::Borrowed("maximum session duration reached")))),
+        None => exchange.await,
+    };
+    result.map_err(|(reason, error)| session.disconnect(token, reason, error))
+}
+
+/// The goodbye `lines` ending in CRLF as the tarpit of `protocol` would close:
+/// ending the headers with the lines as body, as a `421` reply, or on a line of its own after a prompt.
+fn farewell(
+    protocol: Protocol,
+    lines: &[u8],
+) -> Vec<u8> {
+    match protocol {
+        Protocol::Http => {
+            let mut response = format!("Content-Length: {}\r\nConnection: close\r\n\r\n", lines.len()).into_bytes();
+            response.extend_from_slice(lines);
+            response
         },
-        Ok(Err(error)) => {
-          Err(match metrics.disconnect(token) {
-              Ok((connections, connection_time)) => (
-                  connections,
-                  connection_time,
-                  Cow::Owned(format!("{}", error)),
-              ),
-              Err(failure) => (
-                  0usize,
-                  0u64,
-                  Cow::Owned(format!("{}\", \"{}", error, failure)),
-              ),
-          })
+        Protocol::Smtp => {
+            let mut reply = Vec::with_capacity(lines.len() + 8);
+            let mut lines = lines.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).peekable();
+            while let Some(line) = lines.next() {
+                reply.extend_from_slice(if lines.peek().is_some() { b"421-" } else { b"421 " });
+                reply.extend_from_slice(line);
+                reply.push(b'\n');
+            }
+            reply
         },
+        Protocol::Telnet => [b"\r\n", lines].concat(),
+        Protocol::Ssh | Protocol::Sniff => lines.to_vec(),
     }
 }
 
+/// A random response header, e.g. `X-Ks8Ab2: 3Fq0x`.
+fn http_header() -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut word = |length: usize| -> String {
+        (0..length)
+            .map(|_| rng.sample(rand::distributions::Alphanumeric))
+            .collect()
+    };
+    let name = word(8);
+    let value = word(24);
+    format!("X-{}: {}\r\n", name, value).into_bytes()
+}
+
+/// Answer `200 OK` and never finish the headers.
+async fn http(
+    session: &mut Session<'_>,
+    mut token: Token,
+) -> Result<(), Disconnect> {
+    token = session.send_line(token, b"HTTP/1.1 200 OK\r\n").await?;
+    loop {
+        token = session.send_line(token, &http_header()).await?;
+    }
+}
+
+/// Greet with continuation lines of the banner and never send the final `220 ` line.
+async fn smtp(
+    session: &mut Session<'_>,
+    mut token: Token,
+) -> Result<(), Disconnect> {
+    let mut lines = session.start_banner(&token)?;
+    loop {
+        while let Some(line) = lines.next_line() {
+            let mut reply = b"220-".to_vec();
+            reply.extend_from_slice(&template::expand(&line, &session.variables()));
+            token = session.send_line(token, &reply).await?;
+        }
+
+        session.sent_banner(&token, &mut lines)?;
+    }
+}
+
+/// Interpret as command.
+const TELNET_IAC: u8 = 255;
+
+/// Negotiations: `DO`, `DONT`, `WILL` and `WONT`.
+const TELNET_VERBS: &[u8] = &[253, 254, 251, 252];
+
+/// Fragments of a login, sent in turn between the negotiations.
+const TELNET_PROMPTS: &[&[u8]] = &[
+    b"\r\n",
+    b"login: ",
+    b"\r\nPassword: ",
+    b"\r\nLogin incorrect\r\n",
+];
+
+/// Negotiate random telnet options endlessly, interspersed with login prompts.
+async fn telnet(
+    session: &mut Session<'_>,
+    mut token: Token,
+) -> Result<(), Disconnect> {
+    for prompt in TELNET_PROMPTS.iter().cycle() {
+        let negotiations = rand::thread_rng().gen_range(1, 5);
+        for _ in 0..negotiations {
+            let negotiation = {
+                let mut rng = rand::thread_rng();
+                [
+                    TELNET_IAC,
+                    TELNET_VERBS[rng.gen_range(0, TELNET_VERBS.len())],
+                    rng.gen_range(0, 40),
+                ]
+            };
+            token = session.send_line(token, &negotiation).await?;
+        }
+        token = session.send_line(token, prompt).await?;
+
+        session.metrics.sent_banner(&token, "telnet").map_err(|error| session.failed(error))?;
+    }
+    Ok(())
+}
+
 pub(crate) async fn tarpit_connection(
-    mut sock:   tokio::net::TcpStream,
+    sock:       tokio::net::TcpStream,
     peer:       SocketAddr,
-    delay:      Duration,
-    time_out:   Duration,
-    mut token:  Token,
+    protocol:   Protocol,
+    token:      Token,
+    evicted:    oneshot::Receiver<()>,
     metrics:    Arc<Metrics>,
-    banner:     Arc<Vec<u8>>,
-) -> Result<(), &'static str> {
+    tarpit:     Arc<Tarpit>,
+) {
     sock.set_recv_buffer_size(1)
         .unwrap_or_else(|err| warn!("set_recv_buffer_size(), error: {}", err));
 
-    sock.set_send_buffer_size(16)
+    sock.set_send_buffer_size(tarpit.send_buffer_size)
         .unwrap_or_else(|err| warn!("set_send_buffer_size(), error: {}", err));
 
-    'otter: loop {
-        if rand::random::<u8>() == 0x42 {
-            match send_chunk(
-                &mut sock,
-                &delay,
-                &time_out,
-                token,
-                &metrics,
-                b"Meow Meow Meow, but anymeow:\r\n",
-            ).await {
-                Ok(the_token) => {
-                    token = the_token;
-                    metrics.sent_easteregg(&token)?;
-                },
-                Err((connected, connection_time, error)) => {
-                    info!(
-                        "disconnect, peer: {}, duration: {:.2?}, error: \"{}\", clients: {}",
-                        peer,
-                        connection_time,
-                        error,
-                        connected,
-                    );
-                    break 'otter;
-                },
-            }
-        }
+    if tarpit.keepalive.is_some() {
+        sock.set_keepalive(tarpit.keepalive)
+            .unwrap_or_else(|err| warn!("set_keepalive(), error: {}", err));
+    }
 
-        for chunk in banner.chunks(16) {
-            match send_chunk(
-                &mut sock,
-                &delay,
-                &time_out,
-                token,
-                &metrics,
-                chunk,
-            ).await {
-                Ok(the_token) => {
-                    token = the_token;
-                },
-                Err((connected, connection_time, error)) => {
-                    info!(
-                        "disconnect, peer: {}, duration: {:.2?}, error: \"{}\", clients: {}",
-                        peer,
-                        connection_time,
-                        error,
-                        connected,
-                    );
-                    break 'otter;
-                },
+    if let Some(user_timeout) = tarpit.user_timeout {
+        set_user_timeout(&sock, user_timeout)
+            .unwrap_or_else(|err| warn!("set_user_timeout(), error: {}", err));
+    }
+
+    let start = Instant::now();
+    let mut session = Session {
+        sock,
+        peer,
+        id:             template::session_id(),
+        start,
+        deadline:       tarpit.max_duration.and_then(|max_duration| start.checked_add(max_duration)),
+        sent_bytes:     0,
+        paused:         false,
+        protocol,
+        metrics:        &metrics,
+        tarpit:         &tarpit,
+        pacer:          tarpit.pacing.pacer(&peer),
+        identification: Identification::new(),
+        kexinit:        KexInitParser::new(),
+        class:          "unidentified",
+        stage:          "banner",
+        stage_start:    Instant::now(),
+    };
+    info!("session, peer: {}, id: {}", peer, session.id);
+    let result = {
+        let tarpit = async {
+            let (protocol, token) = if protocol == Protocol::Sniff {
+                let (protocol, token) = session.sniff(token).await?;
+                session.protocol = protocol;
+                (protocol, token)
+            } else {
+                (protocol, token)
+            };
+            match protocol {
+                Protocol::Ssh | Protocol::Sniff => ssh(&mut session, token).await,
+                Protocol::Http                  => http(&mut session, token).await,
+                Protocol::Smtp                  => smtp(&mut session, token).await,
+                Protocol::Telnet                => telnet(&mut session, token).await,
             }
+        };
+        futures::pin_mut!(tarpit);
+        match select(tarpit, evicted).await {
+            Either::Left((result, _)) => Some(result),
+            Either::Right((Ok(()), _)) => None,
+            // Disconnected without eviction.
+            Either::Right((Err(_), tarpit)) => Some(tarpit.await),
         }
+    };
+    match result {
+        Some(Err(disconnect)) => session.log(disconnect),
+        Some(Ok(())) => (),
+        None => info!(
+            "evict, peer: {}, session: {}, protocol: {}, duration: {:.2?}, class: {}, clients: {}",
+            session.peer,
+            session.id,
+            session.protocol,
+            session.start.elapsed(),
+            session.class,
+            metrics.connections(),
+        ),
+    }
+}
 
-        metrics.sent_banner(&token)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn farewell_per_protocol() {
+        let lines = b"Bye\r\nfor now\r\n";
+        assert_eq!(farewell(Protocol::Ssh, lines), lines.to_vec());
+        assert_eq!(farewell(Protocol::Http, lines), b"Content-Length: 14\r\nConnection: close\r\n\r\nBye\r\nfor now\r\n".to_vec());
+        assert_eq!(farewell(Protocol::Smtp, lines), b"421-Bye\r\n421 for now\r\n".to_vec());
+        assert_eq!(farewell(Protocol::Telnet, lines), b"\r\nBye\r\nfor now\r\n".to_vec());
     }
-    Ok(())
 }
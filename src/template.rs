@@ -0,0 +1,144 @@
+use rand::Rng;
+use std::{
+    borrow::Cow,
+    net::SocketAddr,
+    time::Duration,
+};
+
+/// Length of a `{random_word}`.
+const RANDOM_WORD_LENGTH: usize = 8;
+
+/// Length of the hexadecimal session id.
+const SESSION_ID_LENGTH: usize = 16;
+
+/// A random id to tell sessions apart, e.g. to trace leaked transcripts back to the connection.
+pub(crate) fn session_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..SESSION_ID_LENGTH)
+        .map(|_| std::char::from_digit(rng.gen_range(0, 16), 16).unwrap_or('0'))
+        .collect()
+}
+
+/// Values of the placeholders of a single connection.
+pub(crate) struct Variables<'a> {
+    pub(crate) peer:          SocketAddr,
+    pub(crate) session_id:    &'a str,
+    pub(crate) uptime:        Duration,
+    pub(crate) connected_for: Duration,
+    pub(crate) clients:       usize,
+    /// Maximum length of an expanded line in bytes, including CRLF.
+    pub(crate) max_length:    usize,
+}
+
+impl Variables<'_> {
+    /// The value of placeholder `name` or `None` for unknown placeholders.
+    fn value(
+        &self,
+        name: &[u8],
+    ) -> Option<String> {
+        Some(match name {
+            b"peer_ip"        => self.peer.ip().to_string(),
+            b"peer_port"      => self.peer.port().to_string(),
+            b"session_id"     => self.session_id.to_owned(),
+            b"uptime"         => self.uptime.as_secs().to_string(),
+            b"connected_for"  => self.connected_for.as_secs().to_string(),
+            b"clients"        => self.clients.to_string(),
+            b"random_word"    => {
+                let mut rng = rand::thread_rng();
+                (0..RANDOM_WORD_LENGTH)
+                    .map(|_| rng.sample(rand::distributions::Alphanumeric))
+                    .collect()
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Replace the placeholders, e.g. `{peer_ip}`, in `line`, unknown placeholders are kept as they are.
+/// Expanded lines longer than `max_length` are truncated at a character boundary, keeping CRLF.
+pub(crate) fn expand<'a>(
+    line: &'a [u8],
+    variables: &Variables<'_>,
+) -> Cow<'a, [u8]> {
+    if !line.contains(&b'{') {
+        return Cow::Borrowed(line);
+    }
+    let mut expanded = Vec::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(open) = rest.iter().position(|&byte| byte == b'{') {
+        expanded.extend_from_slice(&rest[..open]);
+        rest = &rest[open..];
+        let value = rest
+            .iter()
+            .position(|&byte| byte == b'}')
+            .and_then(|close| variables.value(&rest[1..close]).map(|value| (close, value)));
+        match value {
+            Some((close, value)) => {
+                expanded.extend_from_slice(value.as_bytes());
+                rest = &rest[close + 1..];
+            },
+            None => {
+                expanded.push(b'{');
+                rest = &rest[1..];
+            },
+        }
+    }
+    expanded.extend_from_slice(rest);
+    if expanded.starts_with(b"SSH-") {
+        expanded.insert(0, b' ');
+    }
+    if expanded.len() > variables.max_length {
+        let crlf = expanded.ends_with(b"\r\n");
+        let mut end = variables.max_length.saturating_sub(if crlf { 2 } else { 0 });
+        while end > 0 && expanded[end] & 0xc0 == 0x80 {
+            end -= 1;
+        }
+        expanded.truncate(end);
+        if crlf {
+            expanded.extend_from_slice(b"\r\n");
+        }
+    }
+    Cow::Owned(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(max_length: usize) -> Variables<'static> {
+        Variables {
+            peer:           "[2001:db8:ffff:ffff:ffff:ffff:ffff:ffff]:65535".parse().unwrap(),
+            session_id:     "0123456789abcdef",
+            uptime:         Duration::from_secs(3),
+            connected_for:  Duration::from_secs(2),
+            clients:        1,
+            max_length,
+        }
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let variables = variables(255);
+        assert_eq!(
+            &*expand(b"{peer_ip} {peer_port} {session_id} {uptime} {connected_for} {clients}\r\n", &variables),
+            &b"2001:db8:ffff:ffff:ffff:ffff:ffff:ffff 65535 0123456789abcdef 3 2 1\r\n"[..],
+        );
+        assert_eq!(expand(b"{random_word}", &variables).len(), RANDOM_WORD_LENGTH);
+        assert_eq!(&*expand(b"{unknown} {peer_port", &variables), &b"{unknown} {peer_port"[..]);
+        assert!(matches!(expand(b"no placeholders", &variables), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn indent_ssh_prefix() {
+        assert_eq!(&*expand(b"{session_id}", &variables(255)), &b"0123456789abcdef"[..]);
+        assert_eq!(&*expand(b"SSH-{clients}", &variables(255)), &b" SSH-1"[..]);
+    }
+
+    #[test]
+    fn truncate_expanded_lines() {
+        let variables = variables(16);
+        assert_eq!(&*expand(b"{peer_ip}\r\n", &variables), &b"2001:db8:ffff:\r\n"[..]);
+        assert_eq!(&*expand(b"{clients}\xc3\xa4\xc3\xa4\xc3\xa4\xc3\xa4\xc3\xa4\xc3\xa4\xc3\xa4\r\n", &variables), &b"1\xc3\xa4\xc3\xa4\xc3\xa4\xc3\xa4\xc3\xa4\xc3\xa4\r\n"[..]);
+        assert_eq!(&*expand(b"{peer_ip}", &variables), &b"2001:db8:ffff:ff"[..]);
+    }
+}
@@ -0,0 +1,194 @@
+use log::{error, warn};
+use std::{
+    fmt,
+    str::FromStr,
+};
+
+/// What to do with lines starting with `SSH-`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum SshPrefix {
+    /// Refuse to start.
+    Reject,
+    /// Indent the line by a single space.
+    Rewrite,
+}
+
+impl SshPrefix {
+    pub(crate) const VARIANTS: &'static [&'static str] = &["reject", "rewrite"];
+}
+
+impl FromStr for SshPrefix {
+    type Err = String;
+
+    fn from_str(prefix: &str) -> Result<Self, Self::Err> {
+        match prefix {
+            "reject"  => Ok(Self::Reject),
+            "rewrite" => Ok(Self::Rewrite),
+            _ => Err(format!("unknown ssh-prefix handling: {}", prefix)),
+        }
+    }
+}
+
+impl fmt::Display for SshPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Reject  => "reject",
+            Self::Rewrite => "rewrite",
+        })
+    }
+}
+
+/// The shortest line length, fitting CRLF, an indentation, an ASCII and the widest UTF-8 character.
+pub(crate) const MIN_LINE_LENGTH: usize = 4 + 4;
+
+/// Rules for lines sent before the version string, as per RFC 4253.
+pub(crate) struct Validator {
+    /// Maximum length of a line in bytes, including CRLF.
+    max_length: usize,
+    ssh_prefix: SshPrefix,
+}
+
+impl Validator {
+    pub(crate) fn new(
+        max_length: usize,
+        ssh_prefix: SshPrefix,
+    ) -> Self {
+        Self {
+            max_length: max_length.max(MIN_LINE_LENGTH),
+            ssh_prefix,
+        }
+    }
+
+    /// Check every line of `message`, log a diagnostic for each change and return the sanitised message.
+    pub(crate) fn validate(
+        &self,
+        message: &[u8],
+    ) -> Result<String, String> {
+        let mut result = String::with_capacity(message.len());
+        let mut rejected = 0usize;
+        for (number, line) in message.split(|&byte| byte == b'\n').enumerate() {
+            let number = number + 1;
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = match std::str::from_utf8(line) {
+                Ok(line) => line.to_owned(),
+                Err(_) => {
+                    warn!("message, line: {}, warning: \"invalid UTF-8, replaced\"", number);
+                    String::from_utf8_lossy(line).into_owned()
+                },
+            };
+
+            let length = line.len();
+            let line: String = line.chars().filter(|c| !c.is_control()).collect();
+            if line.len() != length {
+                warn!("message, line: {}, warning: \"control characters stripped\"", number);
+            }
+
+            for line in self.wrap(&line, number) {
+                if line.starts_with("SSH-") {
+                    match self.ssh_prefix {
+                        SshPrefix::Reject => {
+                            error!("message, line: {}, error: \"line starts with SSH-\"", number);
+                            rejected += 1;
+                        },
+                        SshPrefix::Rewrite => {
+                            warn!("message, line: {}, warning: \"line starts with SSH-, indented\"", number);
+                            result.push(' ');
+                        },
+                    }
+                }
+                result.push_str(line);
+                result.push('\n');
+            }
+        }
+
+        if message.ends_with(b"\n") {
+            result.pop();
+        }
+
+        if rejected == 0 {
+            Ok(result)
+        } else {
+            Err(format!("message, error: \"{} invalid line(s)\"", rejected))
+        }
+    }
+
+    /// Split `line` at character boundaries and before placeholders like `{peer_ip}`,
+    /// so every part fits into `max_length` with CRLF and a possible indentation.
+    fn wrap<'a>(
+        &self,
+        line: &'a str,
+        number: usize,
+    ) -> Vec<&'a str> {
+        let limit = self.max_length - 3;
+        let mut parts = Vec::new();
+        let mut rest = line;
+        while rest.len() > limit {
+            let mut split = limit;
+            while !rest.is_char_boundary(split) {
+                split -= 1;
+            }
+            if let Some(open) = rest[..split].rfind('{') {
+                if open > 0 && !rest[open..split].contains('}') && rest[split..].contains('}') {
+                    split = open;
+                }
+            }
+            if split == 0 {
+                split = rest.chars().next().map_or(rest.len(), char::len_utf8);
+            }
+            let (part, tail) = rest.split_at(split);
+            parts.push(part);
+            rest = tail;
+        }
+        parts.push(rest);
+        if parts.len() > 1 {
+            warn!(
+                "message, line: {}, warning: \"longer than {} bytes, wrapped into {} lines\"",
+                number,
+                self.max_length,
+                parts.len(),
+            );
+        }
+        parts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_at_char_boundaries() {
+        let validator = Validator::new(8, SshPrefix::Reject);
+        assert_eq!(validator.wrap("abcdefghijk", 1), vec!["abcde", "fghij", "k"]);
+        assert_eq!(validator.wrap("ääää", 1), vec!["ää", "ää"]);
+        assert_eq!(validator.wrap("", 1), vec![""]);
+    }
+
+    #[test]
+    fn wrap_takes_a_char_at_least() {
+        let validator = Validator { max_length: 4, ssh_prefix: SshPrefix::Reject };
+        assert_eq!(validator.wrap("a😀b", 1), vec!["a", "😀", "b"]);
+    }
+
+    #[test]
+    fn wrap_before_placeholders() {
+        let validator = Validator::new(16, SshPrefix::Reject);
+        assert_eq!(validator.wrap("hello {peer_ip} {session_id}", 1), vec!["hello ", "{peer_ip} ", "{session_id}"]);
+        assert_eq!(validator.wrap("{random_word_too_long}", 1), vec!["{random_word_", "too_long}"]);
+    }
+
+    #[test]
+    fn clamp_max_length() {
+        let validator = Validator::new(0, SshPrefix::Reject);
+        assert_eq!(validator.wrap("😀😀", 1), vec!["😀", "😀"]);
+    }
+
+    #[test]
+    fn validate_lines() {
+        let validator = Validator::new(255, SshPrefix::Rewrite);
+        assert_eq!(validator.validate(b"a\x07b\r\nSSH-2.0\n").as_deref(), Ok("ab\n SSH-2.0\n"));
+        let validator = Validator::new(255, SshPrefix::Reject);
+        assert!(validator.validate(b"SSH-2.0").is_err());
+        assert_eq!(validator.validate(b"\xffa").as_deref(), Ok("\u{fffd}a\n"));
+    }
+}